@@ -0,0 +1,106 @@
+use kube::runtime::controller::Action;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::Error;
+
+/// Retry intervals of failed reconciles
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffConfig {
+    /// Delay after the first failure, doubled after each following one
+    pub base: Duration,
+    /// Upper bound of the delay
+    pub max: Duration,
+    /// Fraction of the delay randomly added or removed, so failing objects don't retry in lockstep
+    pub jitter: f64,
+}
+
+impl BackoffConfig {
+    /// Delay before retrying after `failures` consecutive failures, `random` in `[0, 1)` applies the jitter
+    pub fn delay(&self, failures: u32, random: f64) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        let delay = self.base.saturating_mul(1 << exponent).min(self.max);
+        let factor = 1.0 - self.jitter + 2.0 * self.jitter * random;
+        delay.mul_f64(factor).min(self.max)
+    }
+}
+
+/// Failures of an object at a given generation
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackoffEntry {
+    pub failures: u32,
+    pub generation: Option<i64>,
+    /// Delay before the next retry, `None` if the error is permanent and only a change of the object is retried
+    pub retry_in: Option<Duration>,
+    retry_at: Option<Instant>,
+}
+
+impl BackoffEntry {
+    /// Requeue the object when it should be retried
+    pub fn action(&self) -> Action {
+        match self.retry_in {
+            Some(retry_in) => Action::requeue(retry_in),
+            None => Action::await_change(),
+        }
+    }
+}
+
+/// Consecutive reconcile failures of each object, shared by the controllers
+#[derive(Clone, Default)]
+pub struct Backoffs(Arc<Mutex<HashMap<String, BackoffEntry>>>);
+
+impl Backoffs {
+    /// Failures of the object if it is still backing off at this `generation`, with the time left before retrying.
+    /// A new generation is always reconciled right away
+    pub fn pending(&self, key: &str, generation: Option<i64>) -> Option<BackoffEntry> {
+        let entries = self.0.lock().unwrap();
+        let entry = entries.get(key).filter(|entry| entry.generation == generation)?;
+        match entry.retry_at {
+            Some(retry_at) => {
+                let now = Instant::now();
+                if retry_at <= now {
+                    return None;
+                }
+                Some(BackoffEntry {
+                    retry_in: Some(retry_at - now),
+                    ..entry.clone()
+                })
+            }
+            None => Some(entry.clone()),
+        }
+    }
+
+    /// Record a failure of the object, failures of a previous generation are forgotten
+    pub fn failed(&self, key: &str, generation: Option<i64>, config: &BackoffConfig, error: &Error) -> BackoffEntry {
+        let mut entries = self.0.lock().unwrap();
+        let failures = match entries.get(key) {
+            Some(entry) if entry.generation == generation => entry.failures + 1,
+            _ => 1,
+        };
+        let retry_in = if error.is_permanent() {
+            None
+        } else {
+            Some(config.delay(failures, rand::thread_rng().gen()))
+        };
+        let entry = BackoffEntry {
+            failures,
+            generation,
+            retry_in,
+            retry_at: retry_in.map(|retry_in| Instant::now() + retry_in),
+        };
+        entries.insert(key.to_string(), entry.clone());
+        entry
+    }
+
+    /// Forget the failures of the object, `true` if it was backing off
+    pub fn succeeded(&self, key: &str) -> bool {
+        self.0.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Forget the failures of a deleted object, it won't be reconciled anymore
+    pub fn forget(&self, key: &str) {
+        self.0.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,25 @@
+//! Print the CRDs of the controller as YAML.
+//!
+//! Usage: `crdgen [KIND...]`, prints all of them if no kind is given. Example: `crdgen gordo > k8s/base/gordo-crd.yaml`
+use gordo_controller::crd::{crds, crds_yaml};
+use std::env::args;
+use std::process::exit;
+
+fn main() {
+    let kinds: Vec<String> = args().skip(1).map(|kind| kind.to_lowercase()).collect();
+    let crds: Vec<_> = crds()
+        .into_iter()
+        .filter(|crd| kinds.is_empty() || kinds.contains(&crd.spec.names.kind.to_lowercase()))
+        .collect();
+    if crds.is_empty() {
+        eprintln!("No CRD found for kinds: {}", kinds.join(", "));
+        exit(1);
+    }
+    match crds_yaml(&crds) {
+        Ok(yaml) => print!("{}", yaml),
+        Err(err) => {
+            eprintln!("Failed to serialize CRDs: {}", err);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::crd::{argo::Workflow, gordo::Gordo, model::Model};
+use crate::utils::namespaced_apis;
+use futures::future::{join_all, BoxFuture};
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{Api, ListParams, Resource};
+use kube::client::Client;
+use kube::runtime::reflector::{reflector, store::Writer, ObjectRef, Store};
+use kube::runtime::watcher::{self, watcher, Event};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+pub const PROJECT_NAME_LABEL: &str = "applications.gordo.equinor.com/project-name";
+pub const PROJECT_REVISION_LABEL: &str = "applications.gordo.equinor.com/project-revision";
+
+/// (namespace, project name)
+type ProjectKey = (String, String);
+
+fn project_key<K: Resource>(obj: &K) -> Option<ProjectKey> {
+    let meta = obj.meta();
+    match (
+        &meta.namespace,
+        meta.labels.as_ref().and_then(|labels| labels.get(PROJECT_NAME_LABEL)),
+    ) {
+        (Some(namespace), Some(project_name)) => Some((namespace.clone(), project_name.clone())),
+        _ => None,
+    }
+}
+
+/// Object references grouped by their namespace and project-name label
+struct ProjectIndex<K: Resource<DynamicType = ()>> {
+    keys: HashMap<ObjectRef<K>, ProjectKey>,
+    objects: HashMap<ProjectKey, HashSet<ObjectRef<K>>>,
+}
+
+impl<K: Resource<DynamicType = ()>> Default for ProjectIndex<K> {
+    fn default() -> Self {
+        ProjectIndex {
+            keys: HashMap::new(),
+            objects: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Resource<DynamicType = ()>> ProjectIndex<K> {
+    fn remove(&mut self, obj_ref: &ObjectRef<K>) {
+        if let Some(key) = self.keys.remove(obj_ref) {
+            if let Some(obj_refs) = self.objects.get_mut(&key) {
+                obj_refs.remove(obj_ref);
+                if obj_refs.is_empty() {
+                    self.objects.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, obj: &K) {
+        let obj_ref = ObjectRef::from_obj(obj);
+        self.remove(&obj_ref);
+        if let Some(key) = project_key(obj) {
+            self.keys.insert(obj_ref.clone(), key.clone());
+            self.objects.entry(key).or_default().insert(obj_ref);
+        }
+    }
+
+    fn apply_watcher_event(&mut self, event: &Event<K>) {
+        match event {
+            Event::Applied(obj) => self.insert(obj),
+            Event::Deleted(obj) => self.remove(&ObjectRef::from_obj(obj)),
+            Event::Restarted(objs) => {
+                self.keys.clear();
+                self.objects.clear();
+                objs.iter().for_each(|obj| self.insert(obj));
+            }
+        }
+    }
+}
+
+/// Reflector store of a single watched scope, a namespace or the whole cluster
+struct ScopedStore<K: 'static + Resource<DynamicType = ()>> {
+    store: Store<K>,
+    index: Arc<RwLock<ProjectIndex<K>>>,
+    ready: Arc<AtomicBool>,
+}
+
+impl<K: 'static + Resource<DynamicType = ()> + Clone> Clone for ScopedStore<K> {
+    fn clone(&self) -> Self {
+        ScopedStore {
+            store: self.store.clone(),
+            index: self.index.clone(),
+            ready: self.ready.clone(),
+        }
+    }
+}
+
+/// Reflector stores of one resource kind in all watched scopes, with a lookup by project name
+pub struct IndexedStore<K: 'static + Resource<DynamicType = ()>> {
+    scopes: Vec<ScopedStore<K>>,
+}
+
+impl<K: 'static + Resource<DynamicType = ()> + Clone> Clone for IndexedStore<K> {
+    fn clone(&self) -> Self {
+        IndexedStore {
+            scopes: self.scopes.clone(),
+        }
+    }
+}
+
+impl<K> IndexedStore<K>
+where
+    K: 'static + Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug + Send + Sync,
+{
+    /// Build the stores watching these apis and the future which keeps them up to date,
+    /// it has to be polled for the stores to fill up
+    pub fn new(apis: Vec<Api<K>>, lp: ListParams) -> (Self, BoxFuture<'static, ()>) {
+        IndexedStore::from_watchers(apis.into_iter().map(|api| watcher(api, lp.clone())).collect())
+    }
+
+    /// Build the stores from watcher event streams, one per scope
+    pub fn from_watchers<S>(watchers: Vec<S>) -> (Self, BoxFuture<'static, ()>)
+    where
+        S: Stream<Item = watcher::Result<Event<K>>> + Send + 'static,
+    {
+        let mut scopes: Vec<ScopedStore<K>> = vec![];
+        let mut reflectors = vec![];
+        for events in watchers {
+            let writer: Writer<K> = Writer::default();
+            let scope = ScopedStore {
+                store: writer.as_reader(),
+                index: Arc::new(RwLock::new(ProjectIndex::default())),
+                ready: Arc::new(AtomicBool::new(false)),
+            };
+            let index = scope.index.clone();
+            let ready = scope.ready.clone();
+            let kind = K::kind(&()).to_string();
+            let reflector = reflector(writer, events)
+                .inspect_ok(move |event| {
+                    if let Ok(mut index) = index.write() {
+                        index.apply_watcher_event(event);
+                    }
+                    if let Event::Restarted(objs) = event {
+                        if !ready.swap(true, Ordering::SeqCst) {
+                            info!("{} cache is ready with {} objects", kind, objs.len());
+                        }
+                    }
+                })
+                .for_each(|event| async move {
+                    if let Err(err) = event {
+                        warn!("{} watcher error: {:?}", K::kind(&()), err);
+                    }
+                });
+            scopes.push(scope);
+            reflectors.push(reflector);
+        }
+        (IndexedStore { scopes }, join_all(reflectors).map(|_| ()).boxed())
+    }
+
+    /// `true` once every watched scope has been listed at least once
+    pub fn is_ready(&self) -> bool {
+        self.scopes.iter().all(|scope| scope.ready.load(Ordering::SeqCst))
+    }
+
+    /// All cached objects
+    pub fn state(&self) -> Vec<K> {
+        self.scopes
+            .iter()
+            .flat_map(|scope| scope.store.state())
+            .map(|obj| (*obj).clone())
+            .collect()
+    }
+
+    /// Cached objects in a namespace
+    pub fn namespaced(&self, namespace: &str) -> Vec<K> {
+        self.state()
+            .into_iter()
+            .filter(|obj| obj.meta().namespace.as_deref() == Some(namespace))
+            .collect()
+    }
+
+    /// Cached object by its namespace and name
+    pub fn get(&self, namespace: &str, name: &str) -> Option<K> {
+        let obj_ref = ObjectRef::new(name).within(namespace);
+        self.scopes
+            .iter()
+            .find_map(|scope| scope.store.get(&obj_ref))
+            .map(|obj| (*obj).clone())
+    }
+
+    /// Cached objects labelled with this project name in a namespace
+    pub fn by_project(&self, namespace: &str, project_name: &str) -> Vec<K> {
+        let key: ProjectKey = (namespace.to_string(), project_name.to_string());
+        self.scopes
+            .iter()
+            .flat_map(|scope| {
+                let obj_refs: Vec<ObjectRef<K>> = match scope.index.read() {
+                    Ok(index) => index.objects.get(&key).into_iter().flatten().cloned().collect(),
+                    Err(_) => vec![],
+                };
+                obj_refs.into_iter().flat_map(move |obj_ref| scope.store.get(&obj_ref))
+            })
+            .map(|obj| (*obj).clone())
+            .collect()
+    }
+}
+
+/// Shared reflector stores read by the reconciler and the HTTP API instead of listing resources.
+///
+/// They are filled by their own watches: the `Controller`s of kube 0.71 can't be built on existing stores, so they
+/// watch the Gordos and Models separately. Each watch sees the changes at its own pace, the object a reconcile was
+/// triggered with may be newer than the cached one and the API may answer with a slightly stale state. The
+/// reconciles only rely on the cache for the related objects, and requeue until it is ready
+#[derive(Clone)]
+pub struct Cache {
+    pub gordos: IndexedStore<Gordo>,
+    pub models: IndexedStore<Model>,
+    pub workflows: IndexedStore<Workflow>,
+    pub pods: IndexedStore<Pod>,
+}
+
+impl Cache {
+    /// Build the cache for the watched namespaces and the future which keeps it up to date
+    pub fn new(client: &Client, namespaces: &[String]) -> (Self, BoxFuture<'static, ()>) {
+        let project_lp = ListParams::default().labels(PROJECT_NAME_LABEL);
+        let (gordos, gordos_reflector) = IndexedStore::new(namespaced_apis(client, namespaces), ListParams::default());
+        let (models, models_reflector) = IndexedStore::new(namespaced_apis(client, namespaces), ListParams::default());
+        let (workflows, workflows_reflector) =
+            IndexedStore::new(namespaced_apis(client, namespaces), project_lp.clone());
+        let (pods, pods_reflector) = IndexedStore::new(namespaced_apis(client, namespaces), project_lp);
+        let cache = Cache {
+            gordos,
+            models,
+            workflows,
+            pods,
+        };
+        let reflectors = join_all(vec![
+            gordos_reflector,
+            models_reflector,
+            workflows_reflector,
+            pods_reflector,
+        ])
+        .map(|_| ())
+        .boxed();
+        (cache, reflectors)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.gordos.is_ready() && self.models.is_ready() && self.workflows.is_ready() && self.pods.is_ready()
+    }
+}
@@ -1,6 +1,7 @@
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 // Origin here https://github.com/argoproj/argo/blob/master/pkg/apis/workflow/v1alpha1/workflow_types.go#L34
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -35,4 +36,21 @@ pub struct ArgoWorkflowSpec {}
 #[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
 pub struct ArgoWorkflowStatus {
     pub phase: Option<ArgoWorkflowPhase>,
+    /// Steps of the workflow by node ID
+    #[serde(default)]
+    pub nodes: BTreeMap<String, ArgoNodeStatus>,
+}
+
+// Origin here https://github.com/argoproj/argo/blob/master/pkg/apis/workflow/v1alpha1/workflow_types.go#L1268
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArgoNodeStatus {
+    #[serde(default)]
+    pub display_name: String,
+    pub template_name: Option<String>,
+    /// Nodes share the phases of the workflows
+    pub phase: Option<ArgoWorkflowPhase>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub message: Option<String>,
 }
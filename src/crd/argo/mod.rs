@@ -1,16 +1,22 @@
 pub mod argo;
 pub use argo::*;
 
-use crate::crd::metrics::warning_happened;
+use crate::crd::condition::{Condition, UNKNOWN};
+use crate::crd::gordo::gordo::{Gordo, CONDITION_WORKFLOWS_SUCCEEDED};
+use crate::crd::metrics::{observe_model_build, warning_happened};
 use crate::crd::model::{
-    patch_model_status, patch_model_with_default_status, Model, ModelPhase, ModelPodTerminatedStatus,
+    patch_model_status, patch_model_with_default_status, publish_model_event, Model, ModelPhase,
+    ModelPodTerminatedStatus, ModelStatus,
 };
-use crate::crd::pod::{FAILED, POD_MATCH_LABELS};
+use crate::crd::pod::{is_model_pod, FAILED};
+use crate::events::Recorder;
 use chrono::{DateTime, Utc};
 use k8s_openapi::api::core::v1::ContainerStateTerminated;
 use k8s_openapi::api::core::v1::Pod;
 use kube::api::Api;
 use log::{error, info, warn};
+use std::collections::HashMap;
+use tracing::instrument;
 
 pub const WF_MATCH_LABELS: &'static [&'static str] = &[
     "applications.gordo.equinor.com/project-name",
@@ -18,6 +24,10 @@ pub const WF_MATCH_LABELS: &'static [&'static str] = &[
 ];
 
 pub const WF_NUMBER_LABEL: &str = "applications.gordo.equinor.com/project-workflow";
+/// Retry of the failed model builds a workflow was resubmitted for, missing on the original workflows
+pub const WF_RETRY_LABEL: &str = "applications.gordo.equinor.com/model-retry";
+/// Template of the workflow steps building a model, each step is named `model-builder-<model name>`
+pub const MODEL_BUILDER_TEMPLATE: &str = "model-builder";
 
 fn some_of_workflows_in_phases(workflows: &Vec<&Workflow>, phases: Vec<ArgoWorkflowPhase>) -> bool {
     workflows.iter().any(|wf| match &wf.status {
@@ -29,49 +39,208 @@ fn some_of_workflows_in_phases(workflows: &Vec<&Workflow>, phases: Vec<ArgoWorkf
     })
 }
 
+/// `false` without workflows, none of them being in the phases
 fn all_of_workflows_in_phases(workflows: &Vec<&Workflow>, phases: Vec<ArgoWorkflowPhase>) -> bool {
-    workflows.iter().all(|wf| match &wf.status {
-        Some(status) => match &status.phase {
-            Some(status_phase) => (&phases).into_iter().find(|phase| &status_phase == phase).is_some(),
-            None => false,
-        },
-        _ => false,
-    })
+    !workflows.is_empty()
+        && workflows.iter().all(|wf| match &wf.status {
+            Some(status) => match &status.phase {
+                Some(status_phase) => phases.contains(status_phase),
+                None => false,
+            },
+            _ => false,
+        })
 }
 
-fn find_model_workflows<'a>(model: &'a Model, workflows: &'a [Workflow]) -> Vec<&'a Workflow> {
-    //TODO for performance reason we supposed to reimplement this algorithm with BTreeMap
+/// Keep only the latest retry of each workflow, the ones it replaced don't reflect the models anymore
+fn latest_workflow_retries(workflows: Vec<&Workflow>) -> Vec<&Workflow> {
+    let workflow_number = |workflow: &Workflow| {
+        workflow
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(WF_NUMBER_LABEL))
+            .cloned()
+    };
+    let mut latest_retries: HashMap<Option<String>, u32> = HashMap::new();
+    for workflow in workflows.iter() {
+        let latest_retry = latest_retries.entry(workflow_number(workflow)).or_insert(0);
+        *latest_retry = (*latest_retry).max(workflow_retry(workflow));
+    }
     workflows
+        .into_iter()
+        .filter(|workflow| latest_retries.get(&workflow_number(workflow)) == Some(&workflow_retry(workflow)))
+        .collect()
+}
+
+/// `WorkflowsSucceeded` condition of a `Gordo` from the workflows of its current project revision
+pub fn workflows_condition(gordo: &Gordo, workflows: &[Workflow]) -> Condition {
+    let generation = gordo.metadata.generation;
+    let project_revision = gordo.status.as_ref().map(|status| status.project_revision.as_str());
+    let revision_workflows: Vec<&Workflow> = workflows
         .iter()
-        .filter(|workflow| {
-            let workflow_labels = match &workflow.metadata.labels {
-                Some(workflow_labels) => workflow_labels,
-                None => {
-                    return false;
-                }
-            };
-            let model_labels = match &model.metadata.labels {
-                Some(model_labels) => model_labels,
-                None => {
-                    return false;
-                }
-            };
-            let equal_labels = WF_MATCH_LABELS
-                .iter()
-                .all(move |&label_name| workflow_labels.get(label_name) == model_labels.get(label_name));
-            let mut result = equal_labels;
-            if equal_labels {
-                result = match (workflow_labels.get(WF_NUMBER_LABEL), model_labels.get(WF_NUMBER_LABEL)) {
-                    (Some(workflow_wf_number), Some(model_wf_number)) => workflow_wf_number == model_wf_number,
-                    _ => equal_labels,
-                }
+        .filter(|workflow| match (&workflow.metadata.labels, project_revision) {
+            (Some(labels), Some(project_revision)) => {
+                labels.get("applications.gordo.equinor.com/project-name") == gordo.metadata.name.as_ref()
+                    && labels
+                        .get("applications.gordo.equinor.com/project-revision")
+                        .map(String::as_str)
+                        == Some(project_revision)
             }
-            result
+            _ => false,
         })
+        .collect();
+    let revision_workflows = latest_workflow_retries(revision_workflows);
+
+    if revision_workflows.is_empty() {
+        return Condition::new(
+            CONDITION_WORKFLOWS_SUCCEEDED,
+            UNKNOWN,
+            "NoWorkflows",
+            "No workflows found for the current project revision",
+            generation,
+        );
+    }
+    let n_workflows = revision_workflows.len();
+    if some_of_workflows_in_phases(
+        &revision_workflows,
+        vec![
+            ArgoWorkflowPhase::Error,
+            ArgoWorkflowPhase::Failed,
+            ArgoWorkflowPhase::Skipped,
+        ],
+    ) {
+        Condition::from_bool(
+            CONDITION_WORKFLOWS_SUCCEEDED,
+            false,
+            "WorkflowsFailed",
+            &format!("Some of {} workflows failed", n_workflows),
+            generation,
+        )
+    } else if all_of_workflows_in_phases(&revision_workflows, vec![ArgoWorkflowPhase::Succeeded]) {
+        Condition::from_bool(
+            CONDITION_WORKFLOWS_SUCCEEDED,
+            true,
+            "WorkflowsSucceeded",
+            &format!("All {} workflows succeeded", n_workflows),
+            generation,
+        )
+    } else {
+        Condition::from_bool(
+            CONDITION_WORKFLOWS_SUCCEEDED,
+            false,
+            "WorkflowsRunning",
+            &format!("Waiting for {} workflows", n_workflows),
+            generation,
+        )
+    }
+}
+
+/// `true` if the `Workflow` builds this `Model`
+pub fn is_model_workflow(model: &Model, workflow: &Workflow) -> bool {
+    let workflow_labels = match &workflow.metadata.labels {
+        Some(workflow_labels) => workflow_labels,
+        None => {
+            return false;
+        }
+    };
+    let model_labels = match &model.metadata.labels {
+        Some(model_labels) => model_labels,
+        None => {
+            return false;
+        }
+    };
+    let equal_labels = WF_MATCH_LABELS
+        .iter()
+        .all(move |&label_name| workflow_labels.get(label_name) == model_labels.get(label_name));
+    let mut result = equal_labels;
+    if equal_labels {
+        result = match (workflow_labels.get(WF_NUMBER_LABEL), model_labels.get(WF_NUMBER_LABEL)) {
+            (Some(workflow_wf_number), Some(model_wf_number)) => workflow_wf_number == model_wf_number,
+            _ => equal_labels,
+        }
+    }
+    result
+}
+
+/// Retry of the model builds this workflow was submitted for, `0` for the original workflows
+pub fn workflow_retry(workflow: &Workflow) -> u32 {
+    workflow
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(WF_RETRY_LABEL))
+        .and_then(|retry| retry.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Workflows building the current retry of this `Model`
+pub fn find_model_workflows<'a>(model: &'a Model, workflows: &'a [Workflow]) -> Vec<&'a Workflow> {
+    let retries = model.status.as_ref().map_or(0, |status| status.retries);
+    workflows
+        .iter()
+        .filter(|workflow| is_model_workflow(model, workflow) && workflow_retry(workflow) == retries)
         .collect()
 }
 
-fn failed_pods_terminated_statuses<'a>(model: &'a Model, pods: &'a Vec<Pod>) -> Vec<&'a ContainerStateTerminated> {
+/// `true` if the workflow ended without succeeding
+pub fn is_failed_workflow(workflow: &Workflow) -> bool {
+    some_of_workflows_in_phases(
+        &vec![workflow],
+        vec![
+            ArgoWorkflowPhase::Error,
+            ArgoWorkflowPhase::Failed,
+            ArgoWorkflowPhase::Skipped,
+        ],
+    )
+}
+
+/// `true` if the node is the step building the model `model_name`.
+/// The attempts of a retried step are its children, named after it with the attempt number
+pub fn is_model_build_node(node: &ArgoNodeStatus, model_name: &str) -> bool {
+    node.template_name.as_deref() == Some(MODEL_BUILDER_TEMPLATE)
+        && node.display_name == format!("{}-{}", MODEL_BUILDER_TEMPLATE, model_name)
+}
+
+/// Step building this `Model` in its workflows, if they have one yet
+pub fn find_model_build_node<'a>(model: &Model, workflows: &[&'a Workflow]) -> Option<&'a ArgoNodeStatus> {
+    let model_name = model
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get("applications.gordo.equinor.com/model-name"))?;
+    workflows
+        .iter()
+        .flat_map(|workflow| workflow.status.iter())
+        .flat_map(|status| status.nodes.values())
+        .find(|node| is_model_build_node(node, model_name))
+}
+
+/// Phase of a model from the step building it, or from the whole workflows if it has no step yet.
+/// `None` while the model is still being built, or without workflows building it
+pub fn model_build_phase(build_node: Option<&ArgoNodeStatus>, workflows: &Vec<&Workflow>) -> Option<ModelPhase> {
+    if workflows.is_empty() {
+        return None;
+    }
+    let failed_phases = vec![
+        ArgoWorkflowPhase::Error,
+        ArgoWorkflowPhase::Failed,
+        ArgoWorkflowPhase::Skipped,
+    ];
+    match build_node.and_then(|node| node.phase.as_ref()) {
+        Some(phase) if failed_phases.contains(phase) => Some(ModelPhase::Failed),
+        Some(ArgoWorkflowPhase::Succeeded) => Some(ModelPhase::Succeeded),
+        // The step won't run anymore once its workflow failed
+        Some(_) if all_of_workflows_in_phases(workflows, failed_phases.clone()) => Some(ModelPhase::Failed),
+        Some(_) => None,
+        None if some_of_workflows_in_phases(workflows, failed_phases) => Some(ModelPhase::Failed),
+        None if all_of_workflows_in_phases(workflows, vec![ArgoWorkflowPhase::Succeeded]) => {
+            Some(ModelPhase::Succeeded)
+        }
+        None => None,
+    }
+}
+
+fn failed_pods_terminated_statuses<'a>(model: &'a Model, pods: &'a [Pod]) -> Vec<&'a ContainerStateTerminated> {
     pods.iter()
         .filter(|pod| match &pod.status {
             Some(status) => match &status.phase {
@@ -80,18 +249,7 @@ fn failed_pods_terminated_statuses<'a>(model: &'a Model, pods: &'a Vec<Pod>) ->
             },
             None => false,
         })
-        .filter(|pod| {
-            let pod_labels = &pod.metadata.labels;
-            let model_labels = &model.metadata.labels;
-            POD_MATCH_LABELS
-                .iter()
-                .all(|&label_name| match (model_labels, pod_labels) {
-                    (Some(model_labels), Some(pod_labels)) => {
-                        model_labels.get(label_name) == pod_labels.get(label_name)
-                    }
-                    _ => false,
-                })
-        })
+        .filter(|pod| is_model_pod(model, pod))
         .flat_map(|pod| pod.status.as_ref())
         .flat_map(|pod_status| pod_status.container_statuses.as_ref())
         .flat_map(|container_statuses| container_statuses.iter().filter(|status| status.name == "main"))
@@ -100,7 +258,7 @@ fn failed_pods_terminated_statuses<'a>(model: &'a Model, pods: &'a Vec<Pod>) ->
         .collect()
 }
 
-fn last_container_terminated_status(
+pub fn last_container_terminated_status(
     terminated_statuses: Vec<&ContainerStateTerminated>,
 ) -> Option<&ContainerStateTerminated> {
     if terminated_statuses.len() > 0 {
@@ -120,127 +278,152 @@ fn last_container_terminated_status(
     }
 }
 
-pub async fn monitor_wf(model_api: &Api<Model>, workflows: &Vec<Workflow>, models: &Vec<Model>, pods: &Vec<Pod>) -> () {
-    // TODO this function definitely need to be refactored
-    for model in models {
-        let labels = match &model.metadata.labels {
-            Some(labels) => labels,
-            None => {
-                warn!("Model labels field is empty");
-                continue;
+/// Fill the error details of a failed `Model` from the last terminated container of its failed pods
+fn set_failed_pods_details(model: &Model, pods: &[Pod], new_model_status: &mut ModelStatus) {
+    let model_name = match model
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get("applications.gordo.equinor.com/model-name"))
+    {
+        Some(model_name) => model_name,
+        None => return,
+    };
+    let terminated_statuses = failed_pods_terminated_statuses(model, pods);
+    info!(
+        "Found {} failed pods in terminated status which is relates to the model '{}'",
+        terminated_statuses.len(),
+        model_name
+    );
+    let terminated_status = match last_container_terminated_status(terminated_statuses) {
+        Some(terminated_status) => terminated_status,
+        None => return,
+    };
+    new_model_status.code = Some(terminated_status.exit_code);
+    if let Some(message) = &terminated_status.message {
+        let trimmed_message = message.trim_end();
+        if !trimmed_message.is_empty() {
+            let result: serde_json::Result<ModelPodTerminatedStatus> = serde_json::from_str(trimmed_message);
+            match result {
+                Ok(terminated_status_message) => {
+                    info!(
+                        "Last terminated status message {:?} for model '{}'",
+                        terminated_status_message, model_name
+                    );
+                    new_model_status.error_type = terminated_status_message.error_type.clone();
+                    new_model_status.message = terminated_status_message.message.clone();
+                    new_model_status.traceback = terminated_status_message.traceback.clone();
+                }
+                Err(err) => {
+                    warn!(
+                        "Got JSON error where parsing pod's terminated message for the model '{}': {:?}",
+                        model_name, err
+                    );
+                    warning_happened("parse_terminated_message")
+                }
             }
-        };
-        let model_name = match &model.metadata.name {
-            Some(model_name) => model_name,
-            None => {
-                warn!("Pod's field .metadata.name is empty");
-                continue;
+        }
+    }
+}
+
+/// Reflect the phase of the workflows building this `Model` in its status.
+/// Returns the `Model` with its latest status if it was patched
+#[instrument(skip_all)]
+pub async fn monitor_wf(
+    model_api: &Api<Model>,
+    model: &Model,
+    workflows: &[Workflow],
+    pods: &[Pod],
+    recorder: &Recorder,
+) -> Option<Model> {
+    let labels = match &model.metadata.labels {
+        Some(labels) => labels,
+        None => {
+            warn!("Model labels field is empty");
+            return None;
+        }
+    };
+    let model_name = match &model.metadata.name {
+        Some(model_name) => model_name,
+        None => {
+            warn!("Model's field .metadata.name is empty");
+            return None;
+        }
+    };
+    let model_status = match &model.status {
+        Some(model_status) => model_status,
+        None => return None,
+    };
+    let is_reapplied_model = match (
+        &model_status.revision,
+        labels.get("applications.gordo.equinor.com/project-revision"),
+    ) {
+        (Some(status_revision), Some(metadata_revision)) => status_revision != metadata_revision,
+        _ => false,
+    };
+    if is_reapplied_model {
+        return match patch_model_with_default_status(model_api, model).await {
+            Ok(new_model) => {
+                info!(
+                    "Patching Model '{}' from status {:?} to default status {:?}",
+                    model_name, model.status, new_model.status
+                );
+                Some(new_model)
             }
-        };
-        match &model.status {
-            Some(model_status) => {
-                let is_reapplied_model = match (
-                    &model_status.revision,
-                    labels.get("applications.gordo.equinor.com/project-revision"),
-                ) {
-                    (Some(status_revision), Some(metadata_revision)) => status_revision != metadata_revision,
-                    _ => false,
-                };
-                if !is_reapplied_model {
-                    match &model_status.phase {
-                        ModelPhase::InProgress | ModelPhase::Unknown => {
-                            let found_workflows = find_model_workflows(&model, &workflows);
-                            let mut new_model_phase: Option<ModelPhase> = None;
-                            if some_of_workflows_in_phases(
-                                &found_workflows,
-                                vec![
-                                    ArgoWorkflowPhase::Error,
-                                    ArgoWorkflowPhase::Failed,
-                                    ArgoWorkflowPhase::Skipped,
-                                ],
-                            ) {
-                                new_model_phase = Some(ModelPhase::Failed);
-                            } else if all_of_workflows_in_phases(&found_workflows, vec![ArgoWorkflowPhase::Succeeded]) {
-                                new_model_phase = Some(ModelPhase::Succeeded);
-                            }
-                            if let Some(model_phase) = new_model_phase {
-                                let mut new_model_status = model_status.clone();
-                                new_model_status.phase = model_phase.clone();
-                                info!("New phase for the model '{}' will be {:?}", model_name, model_status);
-                                if model_phase == ModelPhase::Failed {
-                                    if let Some(model_name) = labels.get("applications.gordo.equinor.com/model-name") {
-                                        let terminated_statuses = failed_pods_terminated_statuses(&model, &pods);
-                                        info!("Found {} failed pods in terminated status which is relates to the model '{}'", terminated_statuses.len(), model_name);
-                                        if let Some(terminated_status) =
-                                            last_container_terminated_status(terminated_statuses)
-                                        {
-                                            new_model_status.code = Some(terminated_status.exit_code);
-                                            if let Some(message) = &terminated_status.message {
-                                                let trimmed_message = message.trim_end();
-                                                if !trimmed_message.is_empty() {
-                                                    let result: serde_json::Result<ModelPodTerminatedStatus> =
-                                                        serde_json::from_str(&trimmed_message);
-                                                    match result {
-                                                        Ok(terminated_status_message) => {
-                                                            info!(
-                                                                "Last terminated status message {:?} for model '{}'",
-                                                                terminated_status_message, model_name
-                                                            );
-                                                            new_model_status.error_type =
-                                                                terminated_status_message.error_type.clone();
-                                                            new_model_status.message =
-                                                                terminated_status_message.message.clone();
-                                                            new_model_status.traceback =
-                                                                terminated_status_message.traceback.clone();
-                                                        }
-                                                        Err(err) => {
-                                                            warn!("Got JSON error where parsing pod's terminated message for the model '{}': {:?}", model_name, err);
-                                                            warning_happened("parse_terminated_message")
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                if model_phase != model_status.phase {
-                                    match patch_model_status(&model_api, &model_name, &new_model_status).await {
-                                        Ok(new_model) => {
-                                            info!(
-                                                "Patching Model '{}' from status {:?} to {:?}",
-                                                model_name, model.status, new_model.status
-                                            );
-                                        }
-                                        Err(err) => {
-                                            error!(
-                                                "Failed to patch status of Model '{}' - error: {:?}",
-                                                model_name, err
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => (),
-                    }
-                } else {
-                    match patch_model_with_default_status(&model_api, &model).await {
-                        Ok(new_model) => {
-                            info!(
-                                "Patching Model '{}' from status {:?} to default status {:?}",
-                                model_name, model.status, new_model.status
-                            );
-                        }
-                        Err(err) => {
-                            error!(
-                                "Failed to patch status of Model '{}' with default status - error: {:?}",
-                                model_name, err
-                            );
-                        }
-                    }
-                }
+            Err(err) => {
+                error!(
+                    "Failed to patch status of Model '{}' with default status - error: {:?}",
+                    model_name, err
+                );
+                None
             }
-            _ => (),
         };
     }
+    match &model_status.phase {
+        ModelPhase::InProgress | ModelPhase::Unknown => (),
+        _ => return None,
+    }
+
+    // A workflow can build several models, each of them follows its own step when it has one
+    let found_workflows = find_model_workflows(model, workflows);
+    let build_node = find_model_build_node(model, &found_workflows);
+    let mut new_model_status = model_status.clone();
+    if let Some(node) = build_node {
+        new_model_status.started_at = node.started_at.clone();
+        new_model_status.finished_at = node.finished_at.clone();
+    }
+    let is_new_phase = match model_build_phase(build_node, &found_workflows) {
+        Some(model_phase) if model_phase != model_status.phase => {
+            info!("New phase for the model '{}' will be {:?}", model_name, model_phase);
+            new_model_status.phase = model_phase;
+            if new_model_status.phase == ModelPhase::Failed {
+                new_model_status.message = build_node.and_then(|node| node.message.clone());
+                set_failed_pods_details(model, pods, &mut new_model_status);
+            }
+            new_model_status.update_conditions(model.metadata.generation);
+            true
+        }
+        _ => false,
+    };
+    if &new_model_status == model_status {
+        return None;
+    }
+
+    match patch_model_status(model_api, model_name, &new_model_status).await {
+        Ok(new_model) => {
+            info!(
+                "Patching Model '{}' from status {:?} to {:?}",
+                model_name, model.status, new_model.status
+            );
+            if is_new_phase {
+                publish_model_event(recorder, model, &new_model_status).await;
+                observe_model_build(model, &new_model_status);
+            }
+            Some(new_model)
+        }
+        Err(err) => {
+            error!("Failed to patch status of Model '{}' - error: {:?}", model_name, err);
+            None
+        }
+    }
 }
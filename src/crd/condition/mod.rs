@@ -0,0 +1,76 @@
+use chrono::{SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub const TRUE: &str = "True";
+pub const FALSE: &str = "False";
+pub const UNKNOWN: &str = "Unknown";
+
+/// Status condition following the `metav1.Condition` convention
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// One of "True", "False" or "Unknown"
+    pub status: String,
+    pub reason: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(rename = "lastTransitionTime")]
+    pub last_transition_time: String,
+    #[serde(rename = "observedGeneration", default)]
+    pub observed_generation: Option<i64>,
+}
+
+impl Condition {
+    pub fn new(type_: &str, status: &str, reason: &str, message: &str, observed_generation: Option<i64>) -> Self {
+        Condition {
+            type_: type_.to_string(),
+            status: status.to_string(),
+            reason: reason.to_string(),
+            message: message.to_string(),
+            last_transition_time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            observed_generation,
+        }
+    }
+
+    pub fn from_bool(type_: &str, value: bool, reason: &str, message: &str, observed_generation: Option<i64>) -> Self {
+        Condition::new(
+            type_,
+            if value { TRUE } else { FALSE },
+            reason,
+            message,
+            observed_generation,
+        )
+    }
+
+    pub fn is_true(&self) -> bool {
+        self.status == TRUE
+    }
+}
+
+pub fn find_condition<'a>(conditions: &'a [Condition], type_: &str) -> Option<&'a Condition> {
+    conditions.iter().find(|condition| condition.type_ == type_)
+}
+
+/// Add or replace the condition of the same type.
+/// `lastTransitionTime` is kept if the status didn't change, returns `true` if anything besides it changed
+pub fn set_condition(conditions: &mut Vec<Condition>, mut new_condition: Condition) -> bool {
+    match conditions
+        .iter_mut()
+        .find(|condition| condition.type_ == new_condition.type_)
+    {
+        Some(condition) => {
+            if condition.status == new_condition.status {
+                new_condition.last_transition_time = condition.last_transition_time.clone();
+            }
+            let changed = *condition != new_condition;
+            *condition = new_condition;
+            changed
+        }
+        None => {
+            conditions.push(new_condition);
+            true
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use json_patch::{AddOperation, PatchOperation, RemoveOperation, TestOperation};
+use kube::api::{Api, DeleteParams, ListParams, Patch, PatchParams};
+use kube::client::Client;
+use kube::runtime::controller::Action;
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+use crate::cache::PROJECT_NAME_LABEL;
+use crate::crd::argo::Workflow;
+use crate::crd::condition::{set_condition, Condition};
+use crate::crd::gordo::gordo::{patch_gordo_status, remove_gordo_deploy_jobs, Gordo, CONDITION_CLEANED_UP};
+use crate::crd::model::Model;
+use crate::errors::Error;
+use crate::events::{EventType, Recorder};
+
+/// Holds the deletion of a `Gordo` until the resources of its project are removed
+pub const GORDO_CLEANUP_FINALIZER: &str = "gordo.equinor.com/cleanup";
+
+pub fn has_cleanup_finalizer(gordo: &Gordo) -> bool {
+    finalizer_index(gordo).is_some()
+}
+
+fn finalizer_index(gordo: &Gordo) -> Option<usize> {
+    gordo.metadata.finalizers.as_ref().and_then(|finalizers| {
+        finalizers
+            .iter()
+            .position(|finalizer| finalizer == GORDO_CLEANUP_FINALIZER)
+    })
+}
+
+/// Add the cleanup finalizer, keeping the ones set by others
+pub async fn add_cleanup_finalizer(gordo_api: &Api<Gordo>, gordo: &Gordo) -> Result<Gordo, Error> {
+    let name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    let operations = match &gordo.metadata.finalizers {
+        Some(finalizers) if !finalizers.is_empty() => vec![
+            // Fails instead of adding a duplicate if the finalizers changed in the meantime
+            PatchOperation::Test(TestOperation {
+                path: "/metadata/finalizers".to_string(),
+                value: json!(finalizers),
+            }),
+            PatchOperation::Add(AddOperation {
+                path: "/metadata/finalizers/-".to_string(),
+                value: GORDO_CLEANUP_FINALIZER.into(),
+            }),
+        ],
+        _ => vec![
+            PatchOperation::Test(TestOperation {
+                path: "/metadata/finalizers".to_string(),
+                value: Value::Null,
+            }),
+            PatchOperation::Add(AddOperation {
+                path: "/metadata/finalizers".to_string(),
+                value: json!([GORDO_CLEANUP_FINALIZER]),
+            }),
+        ],
+    };
+    gordo_api
+        .patch(
+            name,
+            &PatchParams::default(),
+            &Patch::Json::<()>(json_patch::Patch(operations)),
+        )
+        .await
+        .map_err(Error::KubeError)
+}
+
+/// Remove the cleanup finalizer, letting the API server delete the `Gordo`
+async fn remove_cleanup_finalizer(gordo_api: &Api<Gordo>, gordo: &Gordo) -> Result<(), Error> {
+    let name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    let index = match finalizer_index(gordo) {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    let path = format!("/metadata/finalizers/{}", index);
+    let operations = vec![
+        // Fails instead of removing someone else's finalizer if they were reordered
+        PatchOperation::Test(TestOperation {
+            path: path.clone(),
+            value: GORDO_CLEANUP_FINALIZER.into(),
+        }),
+        PatchOperation::Remove(RemoveOperation { path }),
+    ];
+    gordo_api
+        .patch(
+            name,
+            &PatchParams::default(),
+            &Patch::Json::<()>(json_patch::Patch(operations)),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+/// Request the deletion of every resource of this kind labelled with the project name.
+/// Returns how many of them are still there
+async fn delete_project_resources<K>(api: &Api<K>, gordo_name: &str) -> Result<usize, Error>
+where
+    K: kube::Resource + Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let lp = ListParams::default().labels(&format!("{}={}", PROJECT_NAME_LABEL, gordo_name));
+    let resources = api.list(&lp).await.map_err(Error::KubeError)?;
+    if resources.items.is_empty() {
+        return Ok(0);
+    }
+    let n_deleting = resources
+        .items
+        .iter()
+        .filter(|resource| resource.meta().deletion_timestamp.is_some())
+        .count();
+    if n_deleting < resources.items.len() {
+        api.delete_collection(&DeleteParams::background(), &lp)
+            .await
+            .map_err(Error::KubeError)?;
+    }
+    Ok(resources.items.len())
+}
+
+/// Remove the deploy Jobs, Workflows and Models of a deleted `Gordo`, then release its finalizer.
+/// The progress is reported in the `CleanedUp` condition
+pub async fn cleanup_gordo(
+    gordo: &Gordo,
+    client: &Client,
+    gordo_api: &Api<Gordo>,
+    namespace: &str,
+    recorder: &Recorder,
+) -> Result<Action, Error> {
+    if !has_cleanup_finalizer(gordo) {
+        return Ok(Action::await_change());
+    }
+    let gordo_name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    info!("Cleaning up resources of deleted Gordo '{}'", gordo_name);
+
+    remove_gordo_deploy_jobs(gordo, client, namespace, recorder).await;
+    let workflow_api: Api<Workflow> = Api::namespaced(client.clone(), namespace);
+    let n_workflows = delete_project_resources(&workflow_api, gordo_name).await?;
+    let model_api: Api<Model> = Api::namespaced(client.clone(), namespace);
+    let n_models = delete_project_resources(&model_api, gordo_name).await?;
+
+    let mut status = gordo.status.clone().unwrap_or_default();
+    let cleaned_up = n_workflows == 0 && n_models == 0;
+    let condition = if cleaned_up {
+        Condition::from_bool(
+            CONDITION_CLEANED_UP,
+            true,
+            "CleanedUp",
+            "All workflows and models are deleted",
+            gordo.metadata.generation,
+        )
+    } else {
+        Condition::from_bool(
+            CONDITION_CLEANED_UP,
+            false,
+            "CleaningUp",
+            &format!(
+                "Waiting for {} workflows and {} models to be deleted",
+                n_workflows, n_models
+            ),
+            gordo.metadata.generation,
+        )
+    };
+    if set_condition(&mut status.conditions, condition) {
+        if let Err(err) = patch_gordo_status(gordo_api, gordo_name, &status).await {
+            warn!(
+                "Failed to patch cleanup status of Gordo '{}' - error: {:?}",
+                gordo_name, err
+            );
+        }
+    }
+    if !cleaned_up {
+        info!(
+            "Waiting for {} workflows and {} models of Gordo '{}' to be deleted",
+            n_workflows, n_models, gordo_name
+        );
+        return Ok(Action::requeue(Duration::from_secs(5)));
+    }
+
+    recorder
+        .publish(
+            gordo,
+            EventType::Normal,
+            "CleanedUp",
+            "Deleted the deploy jobs, workflows and models",
+        )
+        .await;
+    remove_cleanup_finalizer(gordo_api, gordo).await?;
+    info!("Released the finalizer of Gordo '{}'", gordo_name);
+    Ok(Action::await_change())
+}
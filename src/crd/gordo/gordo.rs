@@ -1,18 +1,24 @@
+use chrono::{SecondsFormat, Utc};
 use futures::future::join_all;
 use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{
+    Affinity, EnvFromSource, EnvVar, EnvVarSource, LocalObjectReference, ResourceRequirements, Toleration,
+};
 use kube::{
     api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams},
     client::Client,
     CustomResource,
 };
 use log::{debug, error, info};
-use schemars::JsonSchema;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use crate::crd::condition::{set_condition, Condition};
+use crate::crd::job::GORDO_PROJECT_NAME_LABEL;
 use crate::crd::metrics::KUBE_ERRORS;
-use crate::utils::get_revision;
+use crate::events::{EventType, Recorder};
 use crate::{create_deploy_job, Config};
 
 pub type GenerationNumber = Option<u32>;
@@ -20,11 +26,42 @@ pub type GenerationNumber = Option<u32>;
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct GordoConfig {
     #[serde(alias = "machines", default)]
+    #[schemars(rename = "machines", schema_with = "machines_schema")]
     models: Vec<Value>,
     #[serde(default)]
+    #[schemars(schema_with = "globals_schema")]
     globals: Option<Value>,
 }
 
+/// Schema of `config.machines`, the machine configs are YAML documents in string fields
+fn machines_schema(_: &mut SchemaGenerator) -> Schema {
+    serde_json::from_value(json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {
+                "dataset": { "type": "string" },
+                "evaluation": { "type": "string" },
+                "metadata": { "type": "string" },
+                "model": { "type": "string" },
+                "name": { "type": "string" },
+            },
+        },
+    }))
+    .expect("Invalid machines schema")
+}
+
+fn globals_schema(_: &mut SchemaGenerator) -> Schema {
+    serde_json::from_value(json!({
+        "type": "object",
+        "properties": {
+            "model": { "type": "string" },
+            "runtime": { "type": "string" },
+        },
+    }))
+    .expect("Invalid globals schema")
+}
+
 #[derive(CustomResource, Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[kube(
     group = "equinor.com",
@@ -34,29 +71,188 @@ pub struct GordoConfig {
     namespaced
 )]
 #[kube(shortname = "gd")]
+#[kube(
+    printcolumn = r#"{"name": "Model-Count", "type": "integer", "description": "Number of Models defined in this Gordo", "jsonPath": ".status.n-models"}"#,
+    printcolumn = r#"{"name": "Models-Built", "type": "integer", "description": "Number of Models currently built", "jsonPath": ".status.n-models-built"}"#,
+    printcolumn = r#"{"name": "Submitted", "type": "integer", "description": "Number of times this gordo has been submitted by gordo-deploy", "jsonPath": ".status.submission-status.Submitted"}"#,
+    printcolumn = r#"{"name": "Deploy version", "type": "string", "description": "The version of gordo-depoy used", "jsonPath": ".spec.deploy-version"}"#,
+    printcolumn = r#"{"name": "Deploy phase", "type": "string", "description": "Phase of the latest gordo-deploy Job", "jsonPath": ".status.deploy-job.phase"}"#
+)]
 pub struct ConfigMapGeneratorSpec {
     #[serde(rename = "deploy-version")]
     pub deploy_version: String,
     #[serde(rename = "deploy-environment")]
-    pub deploy_environment: Option<HashMap<String, String>>,
+    #[schemars(schema_with = "deploy_environment_schema")]
+    pub deploy_environment: Option<HashMap<String, DeployEnvValue>>,
+    #[serde(rename = "deploy-env-from", default, skip_serializing_if = "Option::is_none")]
+    pub deploy_env_from: Option<Vec<EnvFromSource>>,
     #[serde(rename = "deploy-repository")]
     pub deploy_repository: Option<String>,
     #[serde(rename = "docker-registry")]
     pub docker_registry: Option<String>,
     #[serde(rename = "debug-show-workflow")]
     pub debug_show_workflow: Option<bool>,
+    #[serde(rename = "deploy-pod-template", default, skip_serializing_if = "Option::is_none")]
+    pub deploy_pod_template: Option<DeployPodTemplate>,
+    /// Times a failed model is rebuilt before giving up, no retries if unset
+    #[serde(rename = "max-model-retries", default, skip_serializing_if = "Option::is_none")]
+    pub max_model_retries: Option<u32>,
+    /// Only retry the models which failed with one of these `error_type`, all of them if unset
+    #[serde(rename = "model-retry-error-types", default, skip_serializing_if = "Option::is_none")]
+    pub model_retry_error_types: Option<Vec<String>>,
+    /// Stop acting on the Gordo, and suspend its running workflows, until it is set back to `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspend: Option<bool>,
+    /// Superseded project revisions to keep, overrides `REVISION_RETENTION` field by field
+    #[serde(rename = "revision-retention", default, skip_serializing_if = "Option::is_none")]
+    pub revision_retention: Option<RevisionRetention>,
     pub config: GordoConfig,
 }
 
+/// Value of a `deploy-environment` variable, either a literal or a reference to a Secret or ConfigMap key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DeployEnvValue {
+    Value(String),
+    ValueFrom {
+        #[serde(rename = "valueFrom")]
+        value_from: Box<EnvVarSource>,
+    },
+}
+
+impl DeployEnvValue {
+    /// Environment variable `name` of the gordo-deploy container
+    pub fn env_var(&self, name: &str) -> EnvVar {
+        match self {
+            DeployEnvValue::Value(value) => EnvVar {
+                name: name.to_string(),
+                value: Some(value.clone()),
+                value_from: None,
+            },
+            DeployEnvValue::ValueFrom { value_from } => EnvVar {
+                name: name.to_string(),
+                value: None,
+                value_from: Some(value_from.as_ref().clone()),
+            },
+        }
+    }
+}
+
+impl From<String> for DeployEnvValue {
+    fn from(value: String) -> Self {
+        DeployEnvValue::Value(value)
+    }
+}
+
+impl From<&String> for DeployEnvValue {
+    fn from(value: &String) -> Self {
+        DeployEnvValue::Value(value.clone())
+    }
+}
+
+impl From<&str> for DeployEnvValue {
+    fn from(value: &str) -> Self {
+        DeployEnvValue::Value(value.to_string())
+    }
+}
+
+/// Schema of `deploy-environment`, its values are strings or `valueFrom` objects
+fn deploy_environment_schema(_: &mut SchemaGenerator) -> Schema {
+    serde_json::from_value(json!({
+        "type": "object",
+        "nullable": true,
+        "additionalProperties": {
+            "x-kubernetes-preserve-unknown-fields": true,
+        },
+    }))
+    .expect("Invalid deploy-environment schema")
+}
+
+/// Overrides of the gordo-deploy Job's pod, each field replaces the controller's default as a whole
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeployPodTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_pull_policy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_selector: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerations: Option<Vec<Toleration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<Affinity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_class_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_pull_secrets: Option<Vec<LocalObjectReference>>,
+}
+
+impl DeployPodTemplate {
+    /// This template with the fields it doesn't set taken from `defaults`
+    pub fn merge(&self, defaults: &DeployPodTemplate) -> DeployPodTemplate {
+        DeployPodTemplate {
+            resources: self.resources.clone().or_else(|| defaults.resources.clone()),
+            image_pull_policy: self
+                .image_pull_policy
+                .clone()
+                .or_else(|| defaults.image_pull_policy.clone()),
+            node_selector: self.node_selector.clone().or_else(|| defaults.node_selector.clone()),
+            tolerations: self.tolerations.clone().or_else(|| defaults.tolerations.clone()),
+            affinity: self.affinity.clone().or_else(|| defaults.affinity.clone()),
+            priority_class_name: self
+                .priority_class_name
+                .clone()
+                .or_else(|| defaults.priority_class_name.clone()),
+            image_pull_secrets: self
+                .image_pull_secrets
+                .clone()
+                .or_else(|| defaults.image_pull_secrets.clone()),
+        }
+    }
+}
+
+/// Project revisions whose Models and Workflows are kept, the other superseded revisions are deleted.
+/// A revision is kept if any rule keeps it, all of them are kept if no rule is set
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+pub struct RevisionRetention {
+    /// Number of latest revisions kept, including the current one
+    #[serde(rename = "keep-revisions", default, skip_serializing_if = "Option::is_none")]
+    pub keep_revisions: Option<u32>,
+    /// Revisions deployed less than this many seconds ago are kept
+    #[serde(rename = "max-age-seconds", default, skip_serializing_if = "Option::is_none")]
+    pub max_age_seconds: Option<u64>,
+}
+
+impl RevisionRetention {
+    /// This policy with the fields it doesn't set taken from `defaults`
+    pub fn merge(&self, defaults: &RevisionRetention) -> RevisionRetention {
+        RevisionRetention {
+            keep_revisions: self.keep_revisions.or(defaults.keep_revisions),
+            max_age_seconds: self.max_age_seconds.or(defaults.max_age_seconds),
+        }
+    }
+
+    /// `true` if every revision is kept
+    pub fn keeps_all(&self) -> bool {
+        self.keep_revisions.is_none() && self.max_age_seconds.is_none()
+    }
+}
+
 impl GordoConfig {
     /// Count of models defined in this config
     pub fn n_models(&self) -> usize {
         self.models.len()
     }
+
+    /// Models defined in this config, as found in `config.machines`
+    pub fn models(&self) -> &[Value] {
+        &self.models
+    }
 }
 
 /// Represents the possible 'status' of a Gordo resource
-#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct GordoStatus {
     #[serde(rename = "n-models", default)]
     pub n_models: usize,
@@ -66,6 +262,131 @@ pub struct GordoStatus {
     pub n_models_built: usize,
     #[serde(rename = "project-revision", default)]
     pub project_revision: String,
+    #[serde(rename = "revision-counter", default)]
+    pub revision_counter: u32,
+    #[serde(rename = "deploy-job", default)]
+    pub deploy_job: Option<DeployJobStatus>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<BackoffStatus>,
+    /// Value of the rebuild annotation when the Gordo was last submitted
+    #[serde(rename = "rebuild-requested-at", default, skip_serializing_if = "Option::is_none")]
+    pub rebuild_requested_at: Option<String>,
+    /// Latest deployed project revisions, the current one last
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<RevisionHistoryEntry>,
+}
+
+/// Deployment of a project revision
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct RevisionHistoryEntry {
+    pub revision: String,
+    /// Generation of the Gordo which was deployed
+    pub generation: Option<i64>,
+    #[serde(rename = "deploy-version")]
+    pub deploy_version: String,
+    #[serde(rename = "submitted-at")]
+    pub submitted_at: String,
+    #[serde(rename = "n-models-built", default)]
+    pub n_models_built: usize,
+    #[serde(rename = "n-models-failed", default)]
+    pub n_models_failed: usize,
+    /// Creation time of the first Model of the revision
+    #[serde(rename = "first-model-at", default, skip_serializing_if = "Option::is_none")]
+    pub first_model_at: Option<String>,
+}
+
+impl GordoStatus {
+    /// Append the deployment of a revision to the history, keeping the `limit` latest ones.
+    /// A revision deployed again moves to the end
+    pub fn record_revision(&mut self, entry: RevisionHistoryEntry, limit: usize) {
+        self.history.retain(|previous| previous.revision != entry.revision);
+        self.history.push(entry);
+        if self.history.len() > limit {
+            self.history.drain(..self.history.len() - limit);
+        }
+    }
+
+    /// History entry of the current project revision
+    pub fn current_history_entry(&mut self) -> Option<&mut RevisionHistoryEntry> {
+        let project_revision = &self.project_revision;
+        self.history
+            .iter_mut()
+            .find(|entry| &entry.revision == project_revision)
+    }
+}
+
+pub const CONDITION_DEPLOY_JOB_SUBMITTED: &str = "DeployJobSubmitted";
+pub const CONDITION_WORKFLOWS_SUCCEEDED: &str = "WorkflowsSucceeded";
+pub const CONDITION_ALL_MODELS_BUILT: &str = "AllModelsBuilt";
+pub const CONDITION_CLEANED_UP: &str = "CleanedUp";
+pub const CONDITION_SUSPENDED: &str = "Suspended";
+
+/// Annotation requesting a new deploy of an unchanged `Gordo`, set to the time of the request
+pub const REBUILD_REQUESTED_AT_ANNOTATION: &str = "gordo.equinor.com/rebuild-requested-at";
+
+/// Time of the rebuild requested on the `Gordo`, if it wasn't submitted yet
+pub fn requested_rebuild(gordo: &Gordo) -> Option<&str> {
+    let requested_at = gordo
+        .metadata
+        .annotations
+        .as_ref()?
+        .get(REBUILD_REQUESTED_AT_ANNOTATION)?;
+    // The first deploy of the Gordo doesn't need a rebuild
+    let submitted_at = gordo.status.as_ref()?.rebuild_requested_at.as_ref();
+    if submitted_at == Some(requested_at) {
+        return None;
+    }
+    Some(requested_at)
+}
+
+/// Request a new deploy of the `Gordo`, even if its spec didn't change
+pub async fn request_gordo_rebuild(gordo_resource: &Api<Gordo>, gordo_name: &str) -> kube::Result<Gordo> {
+    let requested_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let patch = json!({ "metadata": { "annotations": { REBUILD_REQUESTED_AT_ANNOTATION: requested_at } } });
+    gordo_resource
+        .patch(gordo_name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+}
+
+/// Represents the lifecycle of the gordo-deploy Job for the current project revision
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct DeployJobStatus {
+    pub phase: DeployPhase,
+    #[serde(rename = "job-name")]
+    pub job_name: String,
+    #[serde(rename = "started-at")]
+    pub started_at: Option<String>,
+    #[serde(rename = "finished-at")]
+    pub finished_at: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Failures of the latest reconciles, present only while the controller backs off from the Gordo
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct BackoffStatus {
+    pub failures: u32,
+    pub reason: String,
+    pub message: String,
+    /// Time of the next reconcile, `None` if the error is only retried once the Gordo changes
+    #[serde(rename = "retry-at")]
+    pub retry_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub enum DeployPhase {
+    #[default]
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl DeployPhase {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, DeployPhase::Succeeded | DeployPhase::Failed)
+    }
 }
 
 impl From<&Gordo> for GordoStatus {
@@ -77,11 +398,23 @@ impl From<&Gordo> for GordoStatus {
             n_models: gordo.spec.config.n_models(),
             n_models_built: gordo_status.n_models_built,
             project_revision: gordo_status.project_revision,
+            revision_counter: gordo_status.revision_counter,
+            deploy_job: gordo_status.deploy_job,
+            conditions: gordo_status.conditions,
+            backoff: gordo_status.backoff,
+            rebuild_requested_at: gordo
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(REBUILD_REQUESTED_AT_ANNOTATION))
+                .cloned()
+                .or(gordo_status.rebuild_requested_at),
+            history: gordo_status.history,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum GordoSubmissionStatus {
     Submitted(GenerationNumber),
 }
@@ -91,63 +424,157 @@ impl Default for GordoSubmissionStatus {
     }
 }
 
-/// Start a gordo-deploy job using this `Gordo`.
-/// Will patch the status of the `Gordo` to reflect the current revision number
+pub async fn patch_gordo_status<'a>(
+    gordo_resource: &'a Api<Gordo>,
+    gordo_name: &'a str,
+    new_status: &GordoStatus,
+) -> kube::Result<Gordo> {
+    let patch_params = PatchParams::default();
+    let patch = json!({ "status": new_status });
+    gordo_resource
+        .patch_status(gordo_name, &patch_params, &Patch::Merge(&patch))
+        .await
+}
+
+/// Set or clear the backoff status of a `Gordo`, leaving the rest of its status untouched
+pub async fn patch_gordo_backoff(
+    gordo_resource: &Api<Gordo>,
+    gordo_name: &str,
+    backoff: Option<&BackoffStatus>,
+) -> kube::Result<Gordo> {
+    let patch = json!({ "status": { "backoff": backoff } });
+    gordo_resource
+        .patch_status(gordo_name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+}
+
+/// Start a gordo-deploy job using this `Gordo` with the given project `revision` and its `revision_counter`.
+/// Will patch the status of the `Gordo` to reflect the current revision number once the job is created,
+/// the Models are only created by the workflows the job generates.
+/// If the job can't be created, only the `DeployJobSubmitted` condition is set, so the deploy is retried.
+/// Returns the `Gordo` with its latest status if it was patched
+#[allow(clippy::too_many_arguments)]
 pub async fn start_gordo_deploy_job(
     gordo: &Gordo,
     client: &Client,
     resource: &Api<Gordo>,
     namespace: &str,
     config: &Config,
-) -> () {
+    recorder: &Recorder,
+    revision: &str,
+    revision_counter: u32,
+) -> kube::Result<Option<Gordo>> {
     // Job manifest for launching this gordo config into a workflow
     debug!("Start gordo deploy Job");
-    let revision = get_revision();
     let gordo_name = gordo.metadata.name.to_owned().unwrap().to_owned();
-    let created_job = create_deploy_job(&gordo, &config);
+    let generation = gordo.metadata.generation;
+    let created_job = create_deploy_job(gordo, config, revision);
     let job = match created_job {
         Some(job) => job,
         None => {
             error!("Job is None");
-            return;
+            return Ok(None);
         }
     };
 
     // Before launching this job, remove previous jobs for this project
-    remove_gordo_deploy_jobs(&gordo, &client, &namespace).await;
+    remove_gordo_deploy_jobs(gordo, client, namespace, recorder).await;
 
+    // Send off job, its progress is followed by `monitor_deploy_job`
     let job_name = job.metadata.name.to_owned().unwrap();
-    // Send off job, later we can add support to watching the job if needed via `jobs.watch(..)`
     info!("Launching job - {}!", job_name);
     let postparams = PostParams::default();
-    let jobs: Api<Job> = Api::namespaced(client.clone(), &namespace);
+    let jobs: Api<Job> = Api::namespaced(client.clone(), namespace);
 
-    match jobs.create(&postparams, &job).await {
-        Ok(job) => info!("Submitted job: {:?}", job.metadata.name),
-        Err(e) => {
-            error!("Failed to submit job with error: {:?}", e);
+    if let Err(e) = jobs.create(&postparams, &job).await {
+        error!("Failed to submit job with error: {:?}", e);
+        let message = format!("Failed to create job {}: {}", job_name, e);
+        recorder
+            .publish(gordo, EventType::Warning, "DeployJobCreationFailed", &message)
+            .await;
+        let mut conditions = gordo.status.clone().unwrap_or_default().conditions;
+        set_condition(
+            &mut conditions,
+            Condition::from_bool(
+                CONDITION_DEPLOY_JOB_SUBMITTED,
+                false,
+                "JobCreationFailed",
+                &message,
+                generation,
+            ),
+        );
+        // The rest of the status is left as it is, the generation is still to be submitted
+        let patch = json!({ "status": { "conditions": conditions } });
+        if let Err(err) = resource
+            .patch_status(&gordo_name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+        {
+            error!("Failed to patch status: {:?}", err);
         }
+        return Err(e);
     }
+    info!("Submitted job: {:?}", job.metadata.name);
+    let message = format!("Job {} is created for revision {}", job_name, revision);
+    recorder
+        .publish(gordo, EventType::Normal, "DeployJobCreated", &message)
+        .await;
 
     let mut status = GordoStatus::from(gordo);
-    status.project_revision = revision;
+    status.project_revision = revision.to_string();
+    status.revision_counter = revision_counter;
+    status.record_revision(
+        RevisionHistoryEntry {
+            revision: revision.to_string(),
+            generation,
+            deploy_version: gordo.spec.deploy_version.clone(),
+            submitted_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            ..RevisionHistoryEntry::default()
+        },
+        config.revision_history_limit,
+    );
+    status.deploy_job = Some(DeployJobStatus {
+        job_name,
+        ..DeployJobStatus::default()
+    });
+    set_condition(
+        &mut status.conditions,
+        Condition::from_bool(CONDITION_DEPLOY_JOB_SUBMITTED, true, "JobCreated", &message, generation),
+    );
 
     // Update the status of this job
     info!("Setting status of this gordo '{}' to '{:?}'", &gordo_name, &status);
-    let patch = json!({ "status": status });
-    match resource
-        .patch_status(&gordo_name, &PatchParams::default(), &Patch::Merge(&patch))
-        .await
-    {
-        Ok(o) => info!("Patched status: {:?}", o.status),
+    match patch_gordo_status(resource, &gordo_name, &status).await {
+        Ok(o) => {
+            info!("Patched status: {:?}", o.status);
+            Ok(Some(o))
+        }
         Err(e) => {
             error!("Failed to patch status: {:?}", e);
+            Ok(None)
         }
-    };
+    }
+}
+
+/// Mark the current generation of this `Gordo` as submitted without starting a new deploy job,
+/// used when its spec still matches the deployed project revision
+pub async fn skip_gordo_deploy_job(gordo: &Gordo, resource: &Api<Gordo>) -> Option<Gordo> {
+    let gordo_name = gordo.metadata.name.to_owned().unwrap();
+    let status = GordoStatus::from(gordo);
+    info!(
+        "Gordo '{}' is unchanged at revision '{}', not starting a deploy job",
+        &gordo_name, &status.project_revision
+    );
+    match patch_gordo_status(resource, &gordo_name, &status).await {
+        Ok(o) => Some(o),
+        Err(e) => {
+            error!("Failed to patch status: {:?}", e);
+            None
+        }
+    }
 }
 
 /// Remove any gordo deploy jobs associated with this `Gordo`
-pub async fn remove_gordo_deploy_jobs(gordo: &Gordo, client: &Client, namespace: &str) -> () {
+pub async fn remove_gordo_deploy_jobs(gordo: &Gordo, client: &Client, namespace: &str, recorder: &Recorder) -> () {
     let gordo_name = gordo.metadata.name.to_owned().unwrap();
     info!("Removing any gordo-deploy jobs for Gordo: '{}'", &gordo_name);
 
@@ -159,7 +586,7 @@ pub async fn remove_gordo_deploy_jobs(gordo: &Gordo, client: &Client, namespace:
                     .items
                     .into_iter()
                     .filter(move |job| match &job.metadata.labels {
-                        Some(labels) => match labels.get("gordoProjectName") {
+                        Some(labels) => match labels.get(GORDO_PROJECT_NAME_LABEL) {
                             Some(project_name) => project_name == &gordo_name,
                             None => false,
                         },
@@ -175,6 +602,14 @@ pub async fn remove_gordo_deploy_jobs(gordo: &Gordo, client: &Client, namespace:
                                             "Successfully requested to delete job: {}, waiting for it to die.",
                                             name
                                         );
+                                        recorder
+                                            .publish(
+                                                gordo,
+                                                EventType::Normal,
+                                                "DeployJobDeleted",
+                                                &format!("Deleted previous job {}", name),
+                                            )
+                                            .await;
 
                                         // Keep trying to get the job, it will fail when it no longer exists.
                                         while let Ok(job) = jobs_api.get(&name).await {
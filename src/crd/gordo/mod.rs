@@ -1,33 +1,74 @@
 use kube::{api::Api, client::Client};
+use log::info;
+use tracing::instrument;
 
+use crate::events::Recorder;
+use crate::utils::get_revision;
 use crate::Config;
 
+pub mod cleanup;
 pub mod gordo;
-pub use gordo::{start_gordo_deploy_job, Gordo, GordoSubmissionStatus};
+pub mod retention;
+pub mod rollback;
+pub mod suspend;
+pub use gordo::{requested_rebuild, skip_gordo_deploy_job, start_gordo_deploy_job, Gordo, GordoSubmissionStatus};
 
+/// Start a gordo-deploy job if the `Gordo` generation or its requested rebuild wasn't submitted yet.
+/// Returns the `Gordo` with its latest status if it was patched
+#[instrument(skip_all)]
 pub async fn handle_gordo_state(
     gordo: &Gordo,
     client: &Client,
     resource: &Api<Gordo>,
     namespace: &str,
     config: &Config,
-) -> Result<(), kube::Error> {
-    let should_start_deploy_job = match gordo.status {
-        Some(ref status) => {
-            match status.submission_status {
-                GordoSubmissionStatus::Submitted(ref generation) => {
-                    // If it's submitted, we only want to launch the job if the GenerationNumber has changed.
-                    generation != &gordo.metadata.generation.map(|v| v as u32)
+    recorder: &Recorder,
+) -> Result<Option<Gordo>, kube::Error> {
+    let rebuild_requested_at = requested_rebuild(gordo);
+    let should_start_deploy_job = rebuild_requested_at.is_some()
+        || match gordo.status {
+            Some(ref status) => {
+                match status.submission_status {
+                    GordoSubmissionStatus::Submitted(ref generation) => {
+                        // If it's submitted, we only want to launch the job if the GenerationNumber has changed.
+                        generation != &gordo.metadata.generation.map(|v| v as u32)
+                    }
                 }
             }
-        }
 
-        // Gordo doesn't have a status, so it must need starting
-        None => true,
-    };
+            // Gordo doesn't have a status, so it must need starting
+            None => true,
+        };
 
     if should_start_deploy_job {
-        crate::crd::gordo::start_gordo_deploy_job(&gordo, &client, &resource, &namespace, &config).await;
+        let (project_revision, mut revision_counter) = match gordo.status {
+            Some(ref status) => (status.project_revision.as_str(), status.revision_counter),
+            None => ("", 0),
+        };
+        if let Some(rebuild_requested_at) = rebuild_requested_at {
+            // The counter gives the unchanged spec a new revision
+            info!(
+                "Rebuild of gordo {:?} requested at {}",
+                gordo.metadata.name, rebuild_requested_at
+            );
+            revision_counter += 1;
+        }
+        let revision = get_revision(&gordo.spec, revision_counter);
+        if revision == project_revision {
+            // Re-applied identical spec, the deployed revision is still up to date
+            return Ok(skip_gordo_deploy_job(gordo, resource).await);
+        }
+        return start_gordo_deploy_job(
+            gordo,
+            client,
+            resource,
+            namespace,
+            config,
+            recorder,
+            &revision,
+            revision_counter,
+        )
+        .await;
     }
-    Ok(())
+    Ok(None)
 }
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Duration, Utc};
+use kube::api::{Api, DeleteParams, ListParams, Resource};
+use kube::client::Client;
+use log::{debug, info};
+use std::collections::HashMap;
+
+use crate::cache::{PROJECT_NAME_LABEL, PROJECT_REVISION_LABEL};
+use crate::crd::argo::Workflow;
+use crate::crd::gordo::gordo::{Gordo, RevisionRetention};
+use crate::crd::model::Model;
+use crate::errors::Error;
+use crate::events::{EventType, Recorder};
+use crate::Config;
+
+/// Retention policy of the `Gordo`, its own fields taking precedence over the controller's
+pub fn revision_retention(gordo: &Gordo, config: &Config) -> RevisionRetention {
+    match &gordo.spec.revision_retention {
+        Some(retention) => retention.merge(&config.revision_retention),
+        None => config.revision_retention.clone(),
+    }
+}
+
+/// Project revisions of the `resources`, with the creation time of their latest resource.
+/// Resources being deleted are left out, so their revision isn't deleted again
+pub fn resource_revisions<K: Resource>(resources: &[K]) -> HashMap<String, DateTime<Utc>> {
+    let mut revisions: HashMap<String, DateTime<Utc>> = HashMap::new();
+    for resource in resources {
+        let meta = resource.meta();
+        if meta.deletion_timestamp.is_some() {
+            continue;
+        }
+        let revision = meta
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(PROJECT_REVISION_LABEL));
+        if let (Some(revision), Some(created_at)) = (revision, &meta.creation_timestamp) {
+            let latest = revisions.entry(revision.clone()).or_insert(created_at.0);
+            *latest = (*latest).max(created_at.0);
+        }
+    }
+    revisions
+}
+
+/// Revisions the `retention` doesn't keep, never the `current_revision`
+pub fn expired_revisions(
+    retention: &RevisionRetention,
+    current_revision: &str,
+    revisions: &HashMap<String, DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    if retention.keeps_all() {
+        return vec![];
+    }
+    // The current revision counts as the latest one, even before its resources are created
+    let mut superseded: Vec<(&String, &DateTime<Utc>)> = revisions
+        .iter()
+        .filter(|(revision, _)| revision.as_str() != current_revision)
+        .collect();
+    superseded.sort_by(|(revision_a, time_a), (revision_b, time_b)| {
+        time_b.cmp(time_a).then_with(|| revision_a.cmp(revision_b))
+    });
+    superseded
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, created_at))| {
+            let kept_by_count = retention
+                .keep_revisions
+                .is_some_and(|keep_revisions| index + 1 < keep_revisions as usize);
+            let kept_by_age = retention
+                .max_age_seconds
+                .is_some_and(|max_age_seconds| now - **created_at < Duration::seconds(max_age_seconds as i64));
+            !kept_by_count && !kept_by_age
+        })
+        .map(|(_, (revision, _))| revision.clone())
+        .collect()
+}
+
+/// Delete the resources of this kind from a project revision
+async fn delete_revision_resources<K>(api: &Api<K>, gordo_name: &str, revision: &str) -> Result<(), Error>
+where
+    K: Resource + Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let lp = ListParams::default().labels(&format!(
+        "{}={},{}={}",
+        PROJECT_NAME_LABEL, gordo_name, PROJECT_REVISION_LABEL, revision
+    ));
+    api.delete_collection(&DeleteParams::background(), &lp)
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+/// Delete the Models and Workflows of the superseded project revisions which the retention policy doesn't keep
+pub async fn collect_superseded_revisions(
+    gordo: &Gordo,
+    client: &Client,
+    namespace: &str,
+    models: &[Model],
+    workflows: &[Workflow],
+    config: &Config,
+    recorder: &Recorder,
+) -> Result<(), Error> {
+    let retention = revision_retention(gordo, config);
+    let current_revision = match &gordo.status {
+        Some(status) if !status.project_revision.is_empty() => &status.project_revision,
+        // Nothing is superseded before the first deploy
+        _ => return Ok(()),
+    };
+    let gordo_name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    let mut revisions = resource_revisions(workflows);
+    for (revision, created_at) in resource_revisions(models) {
+        let latest = revisions.entry(revision).or_insert(created_at);
+        *latest = (*latest).max(created_at);
+    }
+    let expired = expired_revisions(&retention, current_revision, &revisions, Utc::now());
+    if expired.is_empty() {
+        debug!("No superseded revision of gordo '{}' to delete", gordo_name);
+        return Ok(());
+    }
+
+    let model_api: Api<Model> = Api::namespaced(client.clone(), namespace);
+    let workflow_api: Api<Workflow> = Api::namespaced(client.clone(), namespace);
+    for revision in expired.iter() {
+        delete_revision_resources(&model_api, gordo_name, revision).await?;
+        delete_revision_resources(&workflow_api, gordo_name, revision).await?;
+    }
+    let message = format!(
+        "Deleted the Models and Workflows of the superseded revisions {}",
+        expired.join(", ")
+    );
+    info!("Gordo '{}': {}", gordo_name, message);
+    recorder
+        .publish(gordo, EventType::Normal, "RevisionsDeleted", &message)
+        .await;
+    Ok(())
+}
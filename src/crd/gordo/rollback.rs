@@ -0,0 +1,131 @@
+use json_patch::{PatchOperation, RemoveOperation, TestOperation};
+use kube::api::{Api, Patch, PatchParams};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::cache::PROJECT_REVISION_LABEL;
+use crate::crd::gordo::gordo::{patch_gordo_status, Gordo, GordoStatus};
+use crate::crd::model::Model;
+use crate::errors::Error;
+use crate::events::{EventType, Recorder};
+
+/// Annotation requesting the `Gordo` to point back to a previously deployed project revision
+pub const ROLLBACK_TO_ANNOTATION: &str = "gordo.equinor.com/rollback-to";
+
+/// Revision of the rollback requested on the `Gordo`
+pub fn requested_rollback(gordo: &Gordo) -> Option<&str> {
+    gordo
+        .metadata
+        .annotations
+        .as_ref()?
+        .get(ROLLBACK_TO_ANNOTATION)
+        .map(String::as_str)
+}
+
+/// Check that the `Gordo` can be rolled back to the `revision`: it is in its history and some of its Models are
+/// still present
+pub fn check_rollback(gordo: &Gordo, models: &[Model], revision: &str) -> Result<(), String> {
+    let status = gordo.status.clone().unwrap_or_default();
+    if status.project_revision == revision {
+        return Err(format!("revision {} is already the current one", revision));
+    }
+    if !status.history.iter().any(|entry| entry.revision == revision) {
+        return Err(format!("revision {} is not in the history", revision));
+    }
+    let has_models = models.iter().any(|model| {
+        model.metadata.deletion_timestamp.is_none()
+            && model
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(PROJECT_REVISION_LABEL))
+                .map(String::as_str)
+                == Some(revision)
+    });
+    if !has_models {
+        return Err(format!("the models of revision {} are not present anymore", revision));
+    }
+    Ok(())
+}
+
+/// Status of the `Gordo` pointing to the `revision` of its history
+pub fn rolled_back_status(status: &GordoStatus, revision: &str) -> GordoStatus {
+    let mut status = status.clone();
+    status.project_revision = revision.to_string();
+    if let Some(index) = status.history.iter().position(|entry| entry.revision == revision) {
+        let entry = status.history.remove(index);
+        status.history.push(entry);
+    }
+    status
+}
+
+/// Request the `Gordo` to point back to the `revision`
+pub async fn request_gordo_rollback(gordo_api: &Api<Gordo>, name: &str, revision: &str) -> kube::Result<Gordo> {
+    let patch = json!({ "metadata": { "annotations": { ROLLBACK_TO_ANNOTATION: revision } } });
+    gordo_api
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+}
+
+/// Remove the handled rollback request, unless it was replaced in the meantime
+async fn remove_rollback_annotation(gordo_api: &Api<Gordo>, name: &str, revision: &str) -> Result<(), Error> {
+    let path = format!("/metadata/annotations/{}", ROLLBACK_TO_ANNOTATION.replace('/', "~1"));
+    let operations = vec![
+        PatchOperation::Test(TestOperation {
+            path: path.clone(),
+            value: revision.into(),
+        }),
+        PatchOperation::Remove(RemoveOperation { path }),
+    ];
+    gordo_api
+        .patch(
+            name,
+            &PatchParams::default(),
+            &Patch::Json::<()>(json_patch::Patch(operations)),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+    Ok(())
+}
+
+/// Point the `Gordo` back to the revision of its rollback request, if it can be.
+/// Returns the `Gordo` with its latest status if it was patched
+pub async fn handle_gordo_rollback(
+    gordo: &Gordo,
+    gordo_api: &Api<Gordo>,
+    models: &[Model],
+    recorder: &Recorder,
+) -> Result<Option<Gordo>, Error> {
+    let revision = match requested_rollback(gordo) {
+        Some(revision) => revision,
+        None => return Ok(None),
+    };
+    let name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+
+    let new_gordo = match check_rollback(gordo, models, revision) {
+        Ok(()) => {
+            let status = rolled_back_status(&gordo.status.clone().unwrap_or_default(), revision);
+            let new_gordo = patch_gordo_status(gordo_api, name, &status)
+                .await
+                .map_err(Error::KubeError)?;
+            let message = format!("Rolled back to revision {}", revision);
+            info!("Gordo '{}': {}", name, message);
+            recorder.publish(gordo, EventType::Normal, "RolledBack", &message).await;
+            Some(new_gordo)
+        }
+        Err(reason) => {
+            let message = format!("Unable to roll back: {}", reason);
+            warn!("Gordo '{}': {}", name, message);
+            recorder
+                .publish(gordo, EventType::Warning, "RollbackFailed", &message)
+                .await;
+            None
+        }
+    };
+    remove_rollback_annotation(gordo_api, name, revision).await?;
+    Ok(new_gordo)
+}
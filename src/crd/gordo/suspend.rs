@@ -0,0 +1,113 @@
+use kube::api::{Api, Patch, PatchParams};
+use kube::client::Client;
+use log::{error, info};
+use serde_json::json;
+
+use crate::crd::argo::{ArgoWorkflowPhase, Workflow};
+use crate::crd::condition::{find_condition, set_condition, Condition};
+use crate::crd::gordo::gordo::{patch_gordo_status, Gordo, CONDITION_SUSPENDED};
+use crate::errors::Error;
+use crate::events::{EventType, Recorder};
+use crate::Config;
+
+/// `true` if the controller shouldn't act on the `Gordo` until it is resumed
+pub fn is_suspended(gordo: &Gordo) -> bool {
+    gordo.spec.suspend == Some(true)
+}
+
+/// `true` if the `Suspended` condition of the `Gordo` is set
+pub fn has_suspended_condition(gordo: &Gordo) -> bool {
+    gordo
+        .status
+        .as_ref()
+        .and_then(|status| find_condition(&status.conditions, CONDITION_SUSPENDED))
+        .map(Condition::is_true)
+        .unwrap_or(false)
+}
+
+/// `true` if the workflow didn't finish yet, so it can be suspended or resumed
+pub fn is_unfinished_workflow(workflow: &Workflow) -> bool {
+    match workflow.status.as_ref().and_then(|status| status.phase.as_ref()) {
+        Some(ArgoWorkflowPhase::Pending) | Some(ArgoWorkflowPhase::Running) | None => true,
+        Some(_) => false,
+    }
+}
+
+/// Suspend or resume the unfinished workflows, the ones which fail to be patched are only logged
+async fn set_workflows_suspend(client: &Client, namespace: &str, workflows: &[Workflow], suspend: bool) {
+    let workflow_api: Api<Workflow> = Api::namespaced(client.clone(), namespace);
+    // Argo resumes a workflow once `spec.suspend` is removed
+    let patch = json!({ "spec": { "suspend": if suspend { Some(true) } else { None } } });
+    for workflow in workflows.iter().filter(|workflow| is_unfinished_workflow(workflow)) {
+        let name = match &workflow.metadata.name {
+            Some(name) => name,
+            None => continue,
+        };
+        match workflow_api
+            .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+        {
+            Ok(_) => info!("Set suspend of workflow '{}' to {}", name, suspend),
+            Err(err) => error!("Failed to set suspend of workflow '{}' - error: {:?}", name, err),
+        }
+    }
+}
+
+/// Set the `Suspended` condition of the `Gordo` and suspend or resume its workflows, if it changed.
+/// Returns the `Gordo` with its latest status if it was patched
+pub async fn handle_gordo_suspend(
+    gordo: &Gordo,
+    client: &Client,
+    gordo_api: &Api<Gordo>,
+    namespace: &str,
+    workflows: &[Workflow],
+    config: &Config,
+    recorder: &Recorder,
+) -> Result<Option<Gordo>, Error> {
+    let suspended = is_suspended(gordo);
+    if suspended == has_suspended_condition(gordo) {
+        return Ok(None);
+    }
+    let name = gordo
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    if config.suspend_workflows {
+        set_workflows_suspend(client, namespace, workflows, suspended).await;
+    }
+
+    let (reason, message) = if suspended {
+        (
+            "Suspended",
+            "The controller doesn't act on the Gordo until it is resumed",
+        )
+    } else {
+        ("Resumed", "The Gordo was resumed")
+    };
+    let mut status = gordo.status.clone().unwrap_or_default();
+    set_condition(
+        &mut status.conditions,
+        Condition::from_bool(
+            CONDITION_SUSPENDED,
+            suspended,
+            reason,
+            message,
+            gordo.metadata.generation,
+        ),
+    );
+    let new_gordo = patch_gordo_status(gordo_api, name, &status)
+        .await
+        .map_err(Error::KubeError)?;
+    info!("Gordo '{}': {}", name, message);
+    recorder.publish(gordo, EventType::Normal, reason, message).await;
+    Ok(Some(new_gordo))
+}
+
+/// Suspend or resume the `Gordo` through its spec
+pub async fn patch_gordo_suspend(gordo_api: &Api<Gordo>, name: &str, suspend: bool) -> kube::Result<Gordo> {
+    let patch = json!({ "spec": { "suspend": suspend } });
+    gordo_api
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+}
@@ -0,0 +1,172 @@
+use k8s_openapi::api::batch::v1::{Job, JobCondition};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{Api, ListParams};
+use log::{error, info, warn};
+
+use crate::crd::argo::last_container_terminated_status;
+use crate::crd::gordo::gordo::{patch_gordo_status, DeployJobStatus, DeployPhase, Gordo};
+use crate::events::{EventType, Recorder};
+
+pub const GORDO_PROJECT_NAME_LABEL: &str = "gordoProjectName";
+pub const DEPLOY_CONTAINER_NAME: &str = "gordo-deploy";
+
+fn failed_condition(job: &Job) -> Option<&JobCondition> {
+    job.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .and_then(|conditions| {
+            conditions
+                .iter()
+                .find(|condition| condition.type_ == "Failed" && condition.status == "True")
+        })
+}
+
+/// Message of the last terminated gordo-deploy container among the Job's pods
+fn deploy_container_message(pods: &[Pod]) -> Option<String> {
+    let terminated_statuses = pods
+        .iter()
+        .flat_map(|pod| pod.status.as_ref())
+        .flat_map(|pod_status| pod_status.container_statuses.as_ref())
+        .flat_map(|container_statuses| {
+            container_statuses
+                .iter()
+                .filter(|status| status.name == DEPLOY_CONTAINER_NAME)
+        })
+        .flat_map(|container_status| container_status.state.as_ref())
+        .flat_map(|state| state.terminated.as_ref())
+        .collect();
+    last_container_terminated_status(terminated_statuses)
+        .and_then(|terminated_status| terminated_status.message.as_ref())
+        .map(|message| message.trim_end().to_string())
+        .filter(|message| !message.is_empty())
+}
+
+/// Build the `DeployJobStatus` from the gordo-deploy `Job` and the pods it has started
+pub fn deploy_job_status(job: &Job, pods: &[Pod]) -> DeployJobStatus {
+    let job_status = job.status.clone().unwrap_or_default();
+    let failed_condition = failed_condition(job);
+
+    let phase = if job_status.succeeded.unwrap_or(0) > 0 {
+        DeployPhase::Succeeded
+    } else if failed_condition.is_some() {
+        DeployPhase::Failed
+    } else if job_status.active.unwrap_or(0) > 0 {
+        DeployPhase::Running
+    } else {
+        DeployPhase::Pending
+    };
+    let finished_at = job_status
+        .completion_time
+        .or_else(|| failed_condition.and_then(|condition| condition.last_transition_time.clone()));
+    let message = match phase {
+        DeployPhase::Failed => {
+            deploy_container_message(pods).or_else(|| failed_condition.and_then(|condition| condition.message.clone()))
+        }
+        _ => None,
+    };
+
+    DeployJobStatus {
+        phase,
+        job_name: job.metadata.name.clone().unwrap_or_default(),
+        started_at: job_status.start_time.map(|time| time.0.to_rfc3339()),
+        finished_at: finished_at.map(|time| time.0.to_rfc3339()),
+        message,
+    }
+}
+
+/// Follow the gordo-deploy Job of this `Gordo` and reflect its lifecycle in the `Gordo` status.
+/// Returns the `Gordo` with its latest status if it was patched
+pub async fn monitor_deploy_job(
+    gordo_api: &Api<Gordo>,
+    job_api: &Api<Job>,
+    pod_api: &Api<Pod>,
+    gordo: &Gordo,
+    recorder: &Recorder,
+) -> Option<Gordo> {
+    let gordo_name = match &gordo.metadata.name {
+        Some(name) => name,
+        None => {
+            warn!("Gordo does not have a name");
+            return None;
+        }
+    };
+    let status = match &gordo.status {
+        Some(status) => status,
+        None => return None,
+    };
+    let current_deploy_job = match &status.deploy_job {
+        Some(deploy_job) if !deploy_job.phase.is_finished() => deploy_job,
+        _ => return None,
+    };
+
+    let job = match job_api.get_opt(&current_deploy_job.job_name).await {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            warn!(
+                "Deploy job '{}' of Gordo '{}' is not found",
+                current_deploy_job.job_name, gordo_name
+            );
+            return None;
+        }
+        Err(err) => {
+            error!(
+                "Failed to get deploy job '{}' of Gordo '{}' - error: {:?}",
+                current_deploy_job.job_name, gordo_name, err
+            );
+            return None;
+        }
+    };
+
+    let pods: Vec<Pod> = if failed_condition(&job).is_some() {
+        let lp = ListParams::default().labels(&format!("job-name={}", current_deploy_job.job_name));
+        match pod_api.list(&lp).await {
+            Ok(pod_list) => pod_list.items,
+            Err(err) => {
+                error!(
+                    "Failed to list pods of deploy job '{}' - error: {:?}",
+                    current_deploy_job.job_name, err
+                );
+                vec![]
+            }
+        }
+    } else {
+        vec![]
+    };
+
+    let new_deploy_job = deploy_job_status(&job, &pods);
+    if &new_deploy_job != current_deploy_job {
+        info!(
+            "Deploy job '{}' of Gordo '{}' moved from {:?} to {:?}",
+            current_deploy_job.job_name, gordo_name, current_deploy_job.phase, new_deploy_job.phase
+        );
+        match new_deploy_job.phase {
+            DeployPhase::Failed => {
+                let message = format!(
+                    "Job {} failed: {}",
+                    new_deploy_job.job_name,
+                    new_deploy_job.message.as_deref().unwrap_or("unknown error")
+                );
+                recorder
+                    .publish(gordo, EventType::Warning, "DeployJobFailed", &message)
+                    .await;
+            }
+            DeployPhase::Succeeded => {
+                let message = format!("Job {} succeeded", new_deploy_job.job_name);
+                recorder
+                    .publish(gordo, EventType::Normal, "DeployJobSucceeded", &message)
+                    .await;
+            }
+            _ => (),
+        }
+        let mut new_status = status.clone();
+        new_status.deploy_job = Some(new_deploy_job);
+        return match patch_gordo_status(gordo_api, gordo_name, &new_status).await {
+            Ok(new_gordo) => Some(new_gordo),
+            Err(err) => {
+                error!("Failed to patch status of Gordo '{}' - error: {:?}", gordo_name, err);
+                None
+            }
+        };
+    }
+    None
+}
@@ -0,0 +1,76 @@
+use futures::future::BoxFuture;
+use http::{Request, Response};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use super::KUBE_API_DURATION;
+
+/// Resource of a k8s API path, e.g. `workflows` for `/apis/argoproj.io/v1alpha1/namespaces/default/workflows/name`
+pub fn api_resource(path: &str) -> String {
+    let mut segments = path.trim_matches('/').split('/');
+    // Skip the API group and version
+    match segments.next() {
+        Some("api") => {
+            segments.next();
+        }
+        Some("apis") => {
+            segments.next();
+            segments.next();
+        }
+        _ => return "other".to_string(),
+    }
+    let segments: Vec<&str> = segments.collect();
+    match segments.as_slice() {
+        ["namespaces", _namespace, resource, ..] => resource.to_string(),
+        [resource, ..] if !resource.is_empty() => resource.to_string(),
+        _ => "other".to_string(),
+    }
+}
+
+/// Layer of the kube client observing the latency of its calls in `KUBE_API_DURATION`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KubeApiMetricsLayer;
+
+impl<S> Layer<S> for KubeApiMetricsLayer {
+    type Service = KubeApiMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        KubeApiMetrics { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KubeApiMetrics<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for KubeApiMetrics<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // kube names its calls in the request extensions, other requests fall back to their method
+        let verb = match request.extensions().get::<&'static str>() {
+            Some(verb) => verb.to_string(),
+            None => request.method().as_str().to_lowercase(),
+        };
+        let resource = api_resource(request.uri().path());
+        // A watch is only timed until the API server starts streaming its events
+        let timer = KUBE_API_DURATION.with_label_values(&[&verb, &resource]).start_timer();
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await;
+            timer.observe_duration();
+            response
+        })
+    }
+}
@@ -1,5 +1,15 @@
+pub mod kube_api;
+
+use chrono::DateTime;
 use lazy_static::lazy_static;
-use prometheus::{IntCounterVec, Opts, Registry};
+use prometheus::{
+    exponential_buckets, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+};
+use std::time::Instant;
+
+use crate::cache::PROJECT_NAME_LABEL;
+use crate::crd::gordo::Gordo;
+use crate::crd::model::{filter_models_on_gordo, Model, ModelPhase, ModelStatus, MODEL_PHASES};
 
 pub const METRICS_NAMESPACE: &str = "gordo_controller";
 
@@ -29,6 +39,73 @@ lazy_static! {
         &[]
     )
     .unwrap();
+    pub static ref RECONCILE_MODEL_COUNT: IntCounterVec = IntCounterVec::new(
+        Opts::new("reconcile_model_count", "Model reconcile count").namespace(METRICS_NAMESPACE),
+        &["gordo_name"]
+    )
+    .unwrap();
+    pub static ref RECONCILE_MODEL_SUCCEDED: IntCounterVec = IntCounterVec::new(
+        Opts::new("reconcile_model_succeded", "Reconcile Model succeded").namespace(METRICS_NAMESPACE),
+        &[]
+    )
+    .unwrap();
+    pub static ref RECONCILE_MODEL_ERROR: IntCounterVec = IntCounterVec::new(
+        Opts::new("reconcile_model_error", "Reconcile Model errors").namespace(METRICS_NAMESPACE),
+        &[]
+    )
+    .unwrap();
+    pub static ref MODELS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("models", "Models of the current Gordo revisions by phase").namespace(METRICS_NAMESPACE),
+        &["gordo", "namespace", "phase"]
+    )
+    .unwrap();
+    pub static ref GORDO_MODELS_EXPECTED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("gordo_models_expected", "Models defined in the Gordo").namespace(METRICS_NAMESPACE),
+        &["gordo", "namespace"]
+    )
+    .unwrap();
+    pub static ref GORDO_MODELS_BUILT: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("gordo_models_built", "Models of the current Gordo revision built").namespace(METRICS_NAMESPACE),
+        &["gordo", "namespace"]
+    )
+    .unwrap();
+    pub static ref RECONCILE_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "reconcile_duration_seconds",
+            "Reconcile duration by resource and outcome"
+        )
+        .namespace(METRICS_NAMESPACE),
+        &["resource", "outcome"]
+    )
+    .unwrap();
+    pub static ref KUBE_API_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "kube_api_duration_seconds",
+            "k8s API calls latency by verb and resource"
+        )
+        .namespace(METRICS_NAMESPACE),
+        &["verb", "resource"]
+    )
+    .unwrap();
+    pub static ref DEPLOY_FIRST_MODEL_DURATION: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "deploy_first_model_seconds",
+            "Time from the submit of the deploy job to the first Model of the revision"
+        )
+        .namespace(METRICS_NAMESPACE)
+        .buckets(exponential_buckets(10.0, 2.0, 12).unwrap())
+    )
+    .unwrap();
+    pub static ref MODEL_BUILD_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "model_build_duration_seconds",
+            "Model build duration by gordo and final phase"
+        )
+        .namespace(METRICS_NAMESPACE)
+        .buckets(exponential_buckets(30.0, 2.0, 12).unwrap()),
+        &["gordo", "phase"]
+    )
+    .unwrap();
 }
 
 pub fn custom_metrics(registry: &Registry) {
@@ -37,8 +114,94 @@ pub fn custom_metrics(registry: &Registry) {
     registry.register(Box::new(RECONCILE_GORDO_COUNT.clone())).unwrap();
     registry.register(Box::new(RECONCILE_GORDO_SUCCEDED.clone())).unwrap();
     registry.register(Box::new(RECONCILE_GORDO_ERROR.clone())).unwrap();
+    registry.register(Box::new(RECONCILE_MODEL_COUNT.clone())).unwrap();
+    registry.register(Box::new(RECONCILE_MODEL_SUCCEDED.clone())).unwrap();
+    registry.register(Box::new(RECONCILE_MODEL_ERROR.clone())).unwrap();
+    registry.register(Box::new(MODELS.clone())).unwrap();
+    registry.register(Box::new(GORDO_MODELS_EXPECTED.clone())).unwrap();
+    registry.register(Box::new(GORDO_MODELS_BUILT.clone())).unwrap();
+    registry.register(Box::new(RECONCILE_DURATION.clone())).unwrap();
+    registry.register(Box::new(KUBE_API_DURATION.clone())).unwrap();
+    registry
+        .register(Box::new(DEPLOY_FIRST_MODEL_DURATION.clone()))
+        .unwrap();
+    registry.register(Box::new(MODEL_BUILD_DURATION.clone())).unwrap();
 }
 
 pub fn warning_happened(name: &str) {
     WARNINGS.with_label_values(&[name]).inc_by(1);
 }
+
+/// Set the model gauges of the `Gordo` from the models of its current revision
+pub fn update_gordo_metrics(gordo: &Gordo, models: &[Model]) {
+    let (name, namespace) = match (&gordo.metadata.name, &gordo.metadata.namespace) {
+        (Some(name), Some(namespace)) => (name.as_str(), namespace.as_str()),
+        _ => return,
+    };
+    let gordo_models: Vec<&Model> = filter_models_on_gordo(gordo, models).collect();
+    for phase in MODEL_PHASES.iter() {
+        let n_models = gordo_models
+            .iter()
+            .filter(|model| model.status.as_ref().map(|status| &status.phase) == Some(phase))
+            .count();
+        MODELS
+            .with_label_values(&[name, namespace, phase.as_str()])
+            .set(n_models as i64);
+    }
+    let status = gordo.status.clone().unwrap_or_default();
+    GORDO_MODELS_EXPECTED
+        .with_label_values(&[name, namespace])
+        .set(gordo.spec.config.n_models() as i64);
+    GORDO_MODELS_BUILT
+        .with_label_values(&[name, namespace])
+        .set(status.n_models_built as i64);
+}
+
+/// Remove the series of a deleted `Gordo`
+pub fn remove_gordo_metrics(name: &str, namespace: &str) {
+    for phase in MODEL_PHASES.iter() {
+        // Missing series are fine, the Gordo may not have been reconciled by this replica
+        let _ = MODELS.remove_label_values(&[name, namespace, phase.as_str()]);
+    }
+    let _ = GORDO_MODELS_EXPECTED.remove_label_values(&[name, namespace]);
+    let _ = GORDO_MODELS_BUILT.remove_label_values(&[name, namespace]);
+}
+
+/// Observe the duration of a reconcile which started at `started_at`.
+/// `outcome` is one of `success`, `error` or `backoff` when it was skipped to back off from previous failures
+pub fn observe_reconcile(resource: &str, outcome: &str, started_at: Instant) {
+    RECONCILE_DURATION
+        .with_label_values(&[resource, outcome])
+        .observe(started_at.elapsed().as_secs_f64());
+}
+
+/// Seconds between two RFC 3339 timestamps, `None` if one of them can't be parsed
+pub fn seconds_between(start: &str, end: &str) -> Option<f64> {
+    let start = DateTime::parse_from_rfc3339(start).ok()?;
+    let end = DateTime::parse_from_rfc3339(end).ok()?;
+    Some((end - start).num_milliseconds() as f64 / 1000.0)
+}
+
+/// Observe the build duration of a `Model` which reached a final phase
+pub fn observe_model_build(model: &Model, status: &ModelStatus) {
+    match status.phase {
+        ModelPhase::Succeeded | ModelPhase::Failed => (),
+        _ => return,
+    }
+    let gordo = match model
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(PROJECT_NAME_LABEL))
+    {
+        Some(gordo) => gordo,
+        None => return,
+    };
+    if let (Some(started_at), Some(finished_at)) = (&status.started_at, &status.finished_at) {
+        if let Some(seconds) = seconds_between(started_at, finished_at) {
+            MODEL_BUILD_DURATION
+                .with_label_values(&[gordo, status.phase.as_str()])
+                .observe(seconds);
+        }
+    }
+}
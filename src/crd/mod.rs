@@ -1,5 +1,33 @@
 pub mod argo;
+pub mod condition;
 pub mod gordo;
+pub mod job;
 pub mod metrics;
 pub mod model;
 pub mod pod;
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::CustomResourceExt;
+use std::collections::BTreeMap;
+
+/// CustomResourceDefinitions of the resources owned by the controller, generated from their Rust types
+pub fn crds() -> Vec<CustomResourceDefinition> {
+    let mut crds = vec![gordo::Gordo::crd(), model::Model::crd()];
+    for crd in crds.iter_mut() {
+        // Stage label of the production manifests, replaced by the minikube overlay
+        crd.metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert("stage".to_string(), "production".to_string());
+    }
+    crds
+}
+
+/// The CRDs as a multi-document YAML, as found in `k8s/base`
+pub fn crds_yaml(crds: &[CustomResourceDefinition]) -> Result<String, serde_yaml::Error> {
+    let mut documents: Vec<String> = vec![];
+    for crd in crds {
+        documents.push(serde_yaml::to_string(crd)?);
+    }
+    Ok(documents.concat())
+}
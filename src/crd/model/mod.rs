@@ -1,12 +1,18 @@
 pub mod model;
+pub mod retry;
 pub use model::*;
 
-use kube::api::{Api, Patch, PatchParams};
+use chrono::{DateTime, SecondsFormat, Utc};
+use kube::api::Api;
 use log::{error, info, warn};
-use serde_json::json;
+use tracing::instrument;
 
-use crate::crd::gordo::gordo::{Gordo, GordoStatus};
+use crate::crd::argo::{workflows_condition, Workflow};
+use crate::crd::condition::{set_condition, Condition, UNKNOWN};
+use crate::crd::gordo::gordo::{patch_gordo_status, Gordo, CONDITION_ALL_MODELS_BUILT};
+use crate::crd::metrics::{seconds_between, DEPLOY_FIRST_MODEL_DURATION};
 use crate::errors::Error;
+use crate::events::{EventType, Recorder};
 
 pub async fn patch_model_with_default_status<'a>(
     model_resource: &'a Api<Model>,
@@ -20,6 +26,7 @@ pub async fn patch_model_with_default_status<'a>(
         },
         None => None,
     };
+    status.update_conditions(model.metadata.generation);
     match model.metadata.name.to_owned() {
         Some(name) => patch_model_status(model_resource, &name, &status)
             .await
@@ -28,64 +35,148 @@ pub async fn patch_model_with_default_status<'a>(
     }
 }
 
-pub async fn monitor_models(
-    model_api: &Api<Model>,
-    gordo_api: &Api<Gordo>,
-    models: &Vec<Model>,
-    gordos: &Vec<Gordo>,
-) -> () {
-    for model in models.iter() {
-        if let None = model.status {
-            //TODO Update state here
-            //let name = model.spec.config["name"].as_str().unwrap_or("unknown");
-            let name = match model.metadata.name.to_owned() {
-                Some(name) => name,
-                None => {
-                    warn!("Model does not have a name");
-                    continue;
-                }
-            };
-            info!("Unknown status for model {}", name);
-            match patch_model_with_default_status(model_api, &model).await {
-                Ok(new_model) => info!(
-                    "Patching Model '{}' from status {:?} to {:?}",
-                    name, model.status, new_model.status
-                ),
-                Err(err) => {
-                    error!("Failed to patch status of Model '{}' - error: {:?}", name, err);
-                }
-            }
+/// Publish an Event about the `Model` moving to the phase of `new_status`
+pub async fn publish_model_event(recorder: &Recorder, model: &Model, new_status: &ModelStatus) {
+    let event_type = match new_status.phase {
+        ModelPhase::Failed => EventType::Warning,
+        _ => EventType::Normal,
+    };
+    recorder
+        .publish(model, event_type, new_status.reason(), &new_status.phase_message())
+        .await;
+}
+
+/// Patch a `Model` without status with the default one.
+/// Returns the `Model` with its latest status if it was patched
+pub async fn init_model_status(model_api: &Api<Model>, model: &Model) -> Option<Model> {
+    if model.status.is_some() {
+        return None;
+    }
+    let name = match &model.metadata.name {
+        Some(name) => name,
+        None => {
+            warn!("Model does not have a name");
+            return None;
         }
+    };
+    info!("Unknown status for model {}", name);
+    match patch_model_with_default_status(model_api, model).await {
+        Ok(new_model) => {
+            info!(
+                "Patching Model '{}' from status {:?} to {:?}",
+                name, model.status, new_model.status
+            );
+            Some(new_model)
+        }
+        Err(err) => {
+            error!("Failed to patch status of Model '{}' - error: {:?}", name, err);
+            None
+        }
+    }
+}
+
+/// Creation time of the earliest Model of the current revision of the `Gordo`
+pub fn first_model_created_at(gordo: &Gordo, models: &[Model]) -> Option<DateTime<Utc>> {
+    filter_models_on_gordo(gordo, models)
+        .filter_map(|model| model.metadata.creation_timestamp.as_ref())
+        .map(|created_at| created_at.0)
+        .min()
+}
+
+/// The `AllModelsBuilt` condition of a `Gordo`, `Unknown` until its current revision has models
+pub fn models_built_condition(n_models: usize, n_models_built: usize, generation: Option<i64>) -> Condition {
+    if n_models == 0 {
+        return Condition::new(
+            CONDITION_ALL_MODELS_BUILT,
+            UNKNOWN,
+            "NoModels",
+            "No models found for the current project revision",
+            generation,
+        );
     }
+    Condition::from_bool(
+        CONDITION_ALL_MODELS_BUILT,
+        n_models_built >= n_models,
+        if n_models_built >= n_models {
+            "ModelsBuilt"
+        } else {
+            "ModelsPending"
+        },
+        &format!("{} of {} models built", n_models_built, n_models),
+        generation,
+    )
+}
 
-    // Compare each Gordo's n-models-built against the total models currently found for that Gordo
-    for gordo in gordos {
-        let n_models_built = filter_models_on_gordo(&gordo, &models)
+/// Reflect the models and workflows of the `Gordo` in its status.
+/// Returns the `Gordo` with its latest status if it was patched
+#[instrument(skip_all)]
+pub async fn monitor_models(
+    gordo_api: &Api<Gordo>,
+    models: &Vec<Model>,
+    workflows: &[Workflow],
+    gordo: &Gordo,
+) -> Option<Gordo> {
+    // Compare the Gordo's n-models-built against the total models currently found for that Gordo
+    let n_models_in_phase = |phase: ModelPhase| {
+        filter_models_on_gordo(gordo, models)
             .filter(|model| match model.status.as_ref() {
-                Some(status) => status.phase == ModelPhase::Succeeded,
+                Some(status) => status.phase == phase,
                 None => false,
             })
-            .count();
-
-        // If the gordo's current status of built models doesn't match the current models existing
-        // we need to patch its status to reflect the actual models built for it.
-        if gordo.status.clone().unwrap_or_default().n_models_built != n_models_built {
-            let mut status = GordoStatus::from(gordo);
-            status.n_models_built = n_models_built;
+            .count()
+    };
+    let n_models_built = n_models_in_phase(ModelPhase::Succeeded);
+    let n_models_failed = n_models_in_phase(ModelPhase::Failed);
 
-            let patch = json!({ "status": status });
-            let pp = PatchParams::default();
+    // If the gordo's current status of built models doesn't match the current models existing
+    // we need to patch its status to reflect the actual models built for it.
+    let current_status = match gordo.status.as_ref() {
+        Some(status) => status,
+        // Not submitted yet
+        None => return None,
+    };
+    let mut status = current_status.clone();
+    status.n_models_built = n_models_built;
+    let mut first_model_seconds = None;
+    if let Some(entry) = status.current_history_entry() {
+        entry.n_models_built = n_models_built;
+        entry.n_models_failed = n_models_failed;
+        if entry.first_model_at.is_none() {
+            entry.first_model_at = first_model_created_at(gordo, models)
+                .map(|created_at| created_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+            first_model_seconds = entry
+                .first_model_at
+                .as_ref()
+                .and_then(|first_model_at| seconds_between(&entry.submitted_at, first_model_at));
+        }
+    }
+    set_condition(
+        &mut status.conditions,
+        models_built_condition(status.n_models, n_models_built, gordo.metadata.generation),
+    );
+    set_condition(&mut status.conditions, workflows_condition(gordo, workflows));
 
-            let name = match gordo.metadata.name.to_owned() {
-                Some(name) => name,
-                None => {
-                    warn!("Gordo does not have a name");
-                    continue;
-                }
-            };
-            if let Err(err) = gordo_api.patch_status(&name, &pp, &Patch::Merge(&patch)).await {
-                error!("Failed to patch status of Gordo '{}' - error: {:?}", name, err);
+    if &status == current_status {
+        return None;
+    }
+    let name = match gordo.metadata.name.to_owned() {
+        Some(name) => name,
+        None => {
+            warn!("Gordo does not have a name");
+            return None;
+        }
+    };
+    match patch_gordo_status(gordo_api, &name, &status).await {
+        Ok(new_gordo) => {
+            // Only observed once per revision, as the time is kept in its history entry
+            if let Some(seconds) = first_model_seconds.filter(|seconds| *seconds >= 0.0) {
+                DEPLOY_FIRST_MODEL_DURATION.observe(seconds);
             }
+            Some(new_gordo)
+        }
+        Err(err) => {
+            error!("Failed to patch status of Gordo '{}' - error: {:?}", name, err);
+            None
         }
     }
 }
@@ -1,7 +1,8 @@
+use crate::crd::condition::{set_condition, Condition};
 use crate::crd::gordo::Gordo;
 use kube::api::{Api, Patch, PatchParams};
 use kube::CustomResource;
-use schemars::JsonSchema;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
@@ -16,12 +17,36 @@ use serde_json::Value;
     namespaced
 )]
 #[kube(shortname = "gm")]
+#[kube(
+    printcolumn = r#"{"name": "ModelStatus", "type": "string", "description": "Current status of the model", "jsonPath": ".status.phase"}"#,
+    printcolumn = r#"{"name": "Project revision", "type": "string", "description": "Project revision", "jsonPath": ".metadata.labels.applications\\.gordo\\.equinor\\.com/project-revision"}"#,
+    printcolumn = r#"{"name": "Project name", "type": "string", "description": "Project name", "jsonPath": ".metadata.labels.applications\\.gordo\\.equinor\\.com/project-name"}"#,
+    printcolumn = r#"{"name": "Model name", "type": "string", "description": "Name of the model", "jsonPath": ".metadata.labels.applications\\.gordo\\.equinor\\.com/model-name"}"#
+)]
 pub struct ModelSpec {
     #[serde(rename = "gordo-version")]
     pub gordo_version: String,
+    #[schemars(schema_with = "model_config_schema")]
     pub config: Value,
 }
 
+/// Schema of the model config, its sections are YAML documents in string fields
+fn model_config_schema(_: &mut SchemaGenerator) -> Schema {
+    serde_json::from_value(json!({
+        "type": "object",
+        "properties": {
+            "dataset": { "type": "string" },
+            "evaluation": { "type": "string" },
+            "metadata": { "type": "string" },
+            "model": { "type": "string" },
+            "name": { "type": "string" },
+            "project_name": { "type": "string" },
+            "runtime": { "type": "string" },
+        },
+    }))
+    .expect("Invalid model config schema")
+}
+
 /// Represents the possible 'status' of a Gordo resource
 #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct ModelStatus {
@@ -31,6 +56,66 @@ pub struct ModelStatus {
     pub message: Option<String>,
     pub traceback: Option<String>,
     pub revision: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Number of times the build of the model was retried after failing
+    #[serde(default)]
+    pub retries: u32,
+    /// Start of the workflow step building the model
+    pub started_at: Option<String>,
+    /// End of the workflow step building the model
+    pub finished_at: Option<String>,
+}
+
+pub const CONDITION_BUILDING: &str = "Building";
+pub const CONDITION_FAILED: &str = "Failed";
+pub const CONDITION_READY: &str = "Ready";
+
+/// Reason of the conditions and events of failed models, their `error_type` is left to the message
+pub const REASON_BUILD_FAILED: &str = "BuildFailed";
+
+impl ModelStatus {
+    /// Short CamelCase reason of the current phase, `BuildFailed` for failed models whatever their `error_type`
+    pub fn reason(&self) -> &'static str {
+        match &self.phase {
+            ModelPhase::Failed => REASON_BUILD_FAILED,
+            phase => phase.as_str(),
+        }
+    }
+
+    /// Human readable description of the current phase, with the `error_type` of failed models
+    pub fn phase_message(&self) -> String {
+        match (&self.phase, &self.error_type, &self.message) {
+            (ModelPhase::Failed, Some(error_type), Some(message)) => format!("{}: {}", error_type, message),
+            (ModelPhase::Failed, Some(error_type), None) => error_type.clone(),
+            (ModelPhase::Failed, None, message) => message.clone().unwrap_or_default(),
+            (phase, _, _) => format!("Model is in phase {:?}", phase),
+        }
+    }
+
+    /// Keep the `Building`, `Failed` and `Ready` conditions in line with the current phase
+    pub fn update_conditions(&mut self, observed_generation: Option<i64>) {
+        let (building, failed, ready) = match self.phase {
+            ModelPhase::Unknown => (false, false, false),
+            ModelPhase::InProgress => (true, false, false),
+            ModelPhase::Failed => (false, true, false),
+            ModelPhase::Succeeded => (false, false, true),
+        };
+        let reason = self.reason().to_string();
+        let message = self.phase_message();
+        set_condition(
+            &mut self.conditions,
+            Condition::from_bool(CONDITION_BUILDING, building, &reason, &message, observed_generation),
+        );
+        set_condition(
+            &mut self.conditions,
+            Condition::from_bool(CONDITION_FAILED, failed, &reason, &message, observed_generation),
+        );
+        set_condition(
+            &mut self.conditions,
+            Condition::from_bool(CONDITION_READY, ready, &reason, &message, observed_generation),
+        );
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -46,6 +131,23 @@ pub enum ModelPhase {
 }
 
 pub const PHASES_COUNT: usize = 4;
+pub const MODEL_PHASES: [ModelPhase; PHASES_COUNT] = [
+    ModelPhase::Unknown,
+    ModelPhase::InProgress,
+    ModelPhase::Failed,
+    ModelPhase::Succeeded,
+];
+
+impl ModelPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelPhase::Unknown => "Unknown",
+            ModelPhase::InProgress => "InProgress",
+            ModelPhase::Failed => "Failed",
+            ModelPhase::Succeeded => "Succeeded",
+        }
+    }
+}
 
 impl Default for ModelPhase {
     fn default() -> Self {
@@ -0,0 +1,158 @@
+use kube::api::{Api, PostParams};
+use kube::client::Client;
+use kube::core::{ApiResource, DynamicObject};
+use log::{error, info, warn};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::crd::argo::{find_model_workflows, is_failed_workflow, Workflow, WF_RETRY_LABEL};
+use crate::crd::gordo::Gordo;
+use crate::crd::model::{patch_model_status, Model, ModelPhase, ModelStatus};
+use crate::events::{EventType, Recorder};
+
+/// Name of the workflow submitted by gordo-deploy which a retry workflow was copied from
+pub const WF_RETRY_OF_ANNOTATION: &str = "applications.gordo.equinor.com/retry-of";
+
+/// `true` if the failed build of the model should be retried under the retry settings of its `Gordo`
+pub fn should_retry_model(status: &ModelStatus, gordo: &Gordo) -> bool {
+    if status.phase != ModelPhase::Failed || status.retries >= gordo.spec.max_model_retries.unwrap_or(0) {
+        return false;
+    }
+    match &gordo.spec.model_retry_error_types {
+        Some(error_types) => match &status.error_type {
+            Some(error_type) => error_types.contains(error_type),
+            None => false,
+        },
+        None => true,
+    }
+}
+
+/// Copy of a failed workflow submitted again for the `retry` of its failed models
+pub fn retry_workflow(workflow: &DynamicObject, retry: u32) -> DynamicObject {
+    let original_name = workflow
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(WF_RETRY_OF_ANNOTATION))
+        .or(workflow.metadata.name.as_ref())
+        .cloned()
+        .unwrap_or_default();
+    let mut labels = workflow.metadata.labels.clone().unwrap_or_default();
+    labels.insert(WF_RETRY_LABEL.to_string(), retry.to_string());
+    let mut annotations = BTreeMap::new();
+    annotations.insert(WF_RETRY_OF_ANNOTATION.to_string(), original_name.clone());
+
+    let mut new_workflow = DynamicObject::new(
+        &format!("{}-retry-{}", original_name, retry),
+        &ApiResource::erase::<Workflow>(&()),
+    );
+    new_workflow.metadata.namespace = workflow.metadata.namespace.clone();
+    new_workflow.metadata.labels = Some(labels);
+    new_workflow.metadata.annotations = Some(annotations);
+    new_workflow.metadata.owner_references = workflow.metadata.owner_references.clone();
+    new_workflow.data = json!({ "spec": workflow.data.get("spec").cloned().unwrap_or_else(|| json!({})) });
+    new_workflow
+}
+
+/// Resubmit the failed workflows of this `Model` for its next retry, unless another model of the same
+/// workflows already did. Returns the number of workflows submitted for the retry, the ones already submitted
+/// included
+async fn submit_retry_workflows(
+    workflow_api: &Api<DynamicObject>,
+    model: &Model,
+    workflows: &[Workflow],
+    retry: u32,
+) -> Result<usize, kube::Error> {
+    let mut n_submitted = 0;
+    for workflow in find_model_workflows(model, workflows) {
+        if !is_failed_workflow(workflow) {
+            continue;
+        }
+        let name = match &workflow.metadata.name {
+            Some(name) => name,
+            None => continue,
+        };
+        let new_workflow = retry_workflow(&workflow_api.get(name).await?, retry);
+        match workflow_api.create(&PostParams::default(), &new_workflow).await {
+            Ok(_) => info!(
+                "Submitted workflow {:?} to retry {:?}",
+                new_workflow.metadata.name, name
+            ),
+            Err(kube::Error::Api(err)) if err.code == 409 => {
+                info!("Workflow {:?} was already submitted", new_workflow.metadata.name)
+            }
+            Err(err) => return Err(err),
+        }
+        n_submitted += 1;
+    }
+    Ok(n_submitted)
+}
+
+/// Rebuild a failed `Model` by resubmitting its workflows, as long as its `Gordo` allows more retries.
+/// Returns the `Model` with its latest status if it was patched
+pub async fn retry_failed_model(
+    client: &Client,
+    model_api: &Api<Model>,
+    model: &Model,
+    workflows: &[Workflow],
+    gordo: &Gordo,
+    recorder: &Recorder,
+) -> Option<Model> {
+    let status = model.status.as_ref()?;
+    if !should_retry_model(status, gordo) {
+        return None;
+    }
+    let (namespace, name) = match (&model.metadata.namespace, &model.metadata.name) {
+        (Some(namespace), Some(name)) => (namespace, name),
+        _ => {
+            warn!("Model does not have a namespace or a name");
+            return None;
+        }
+    };
+    let retry = status.retries + 1;
+    let workflow_api: Api<DynamicObject> =
+        Api::namespaced_with(client.clone(), namespace, &ApiResource::erase::<Workflow>(&()));
+    match submit_retry_workflows(&workflow_api, model, workflows, retry).await {
+        Ok(0) => {
+            // The workflows shared with other models may still be running, the Model stays on its current retry
+            info!("No failed workflow to resubmit for Model '{}' yet", name);
+            return None;
+        }
+        Ok(_) => (),
+        Err(err) => {
+            error!(
+                "Failed to resubmit the workflows of Model '{}' - error: {:?}",
+                name, err
+            );
+            return None;
+        }
+    }
+
+    let mut new_status = ModelStatus {
+        phase: ModelPhase::InProgress,
+        retries: retry,
+        revision: status.revision.clone(),
+        conditions: status.conditions.clone(),
+        ..ModelStatus::default()
+    };
+    new_status.update_conditions(model.metadata.generation);
+    let message = format!(
+        "Retry {} of {} after {}",
+        retry,
+        gordo.spec.max_model_retries.unwrap_or(0),
+        status.error_type.as_deref().unwrap_or_else(|| status.reason())
+    );
+    match patch_model_status(model_api, name, &new_status).await {
+        Ok(new_model) => {
+            info!("Retrying the build of Model '{}': {}", name, message);
+            recorder
+                .publish(model, EventType::Normal, "RetryingBuild", &message)
+                .await;
+            Some(new_model)
+        }
+        Err(err) => {
+            error!("Failed to patch status of Model '{}' - error: {:?}", name, err);
+            None
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use kube::api::Api;
-use log::{error, info, warn};
+use log::{error, info};
+use tracing::instrument;
 
-use crate::crd::model::{patch_model_status, Model, ModelPhase, ModelStatus};
+use crate::crd::model::{patch_model_status, publish_model_event, Model, ModelPhase, ModelStatus};
+use crate::events::Recorder;
 use k8s_openapi::api::core::v1::Pod;
 
 pub const PENDING: &str = "Pending";
@@ -16,105 +18,82 @@ pub const POD_MATCH_LABELS: &'static [&'static str] = &[
     "applications.gordo.equinor.com/model-name",
 ];
 
-async fn update_model_status(model_resource: &Api<Model>, model: &Model, new_status: &ModelStatus) {
+async fn update_model_status(
+    model_resource: &Api<Model>,
+    model: &Model,
+    new_status: &ModelStatus,
+    recorder: &Recorder,
+) -> Option<Model> {
     let name = match &model.metadata.name {
         Some(name) => name,
         None => {
             error!("Model metadata.name is empty");
-            return;
+            return None;
         }
     };
-    match patch_model_status(model_resource, name, &new_status).await {
-        Ok(new_model) => info!(
-            "Patching Model '{}' from status {:?} to {:?}",
-            name, model.status, new_model.status
-        ),
+    match patch_model_status(model_resource, name, new_status).await {
+        Ok(new_model) => {
+            info!(
+                "Patching Model '{}' from status {:?} to {:?}",
+                name, model.status, new_model.status
+            );
+            publish_model_event(recorder, model, new_status).await;
+            Some(new_model)
+        }
         Err(err) => {
             error!("Failed to patch status of Model '{}' - error: {:?}", name, err);
+            None
         }
     }
 }
 
-pub async fn monitor_pods(model_api: &Api<Model>, models: &Vec<Model>, pods: &Vec<Pod>) -> () {
-    //Filtering only active models
-    let actual_models: Vec<_> = models
-        .into_iter()
-        .filter(|model| match &model.status {
-            Some(status) => status.phase == ModelPhase::Unknown || status.phase == ModelPhase::InProgress,
-            None => true,
-        })
-        .collect();
-    if actual_models.is_empty() {
-        return;
+/// `true` if the `Pod` belongs to the build of this `Model`
+pub fn is_model_pod(model: &Model, pod: &Pod) -> bool {
+    match (&model.metadata.labels, &pod.metadata.labels) {
+        (Some(model_labels), Some(pod_labels)) => POD_MATCH_LABELS
+            .iter()
+            .all(|&label_name| model_labels.get(label_name) == pod_labels.get(label_name)),
+        _ => false,
     }
+}
+
+/// Reflect the phase of the pods building this `Model` in its status.
+/// Returns the `Model` with its latest status if it was patched
+#[instrument(skip_all)]
+pub async fn monitor_pods(model_api: &Api<Model>, model: &Model, pods: &[Pod], recorder: &Recorder) -> Option<Model> {
+    //Only active models
+    let status = match &model.status {
+        Some(status) if status.phase == ModelPhase::Unknown || status.phase == ModelPhase::InProgress => status,
+        _ => return None,
+    };
 
-    //TODO to perform the models-pods matching in O(1) makes sense to do collect into some sort of HashMap here
-    let actual_pods_labels: Vec<_> = pods
+    let pods_phases: Vec<_> = pods
         .iter()
-        .filter(|pod| match pod.metadata.labels.to_owned() {
+        .filter(|pod| match &pod.metadata.labels {
             Some(labels) => labels.get("applications.gordo.equinor.com/model-name").is_some(),
             None => false,
         })
-        .flat_map(|pod| match pod.status.to_owned() {
-            Some(status) => match status.phase {
-                Some(phase) => {
-                    if phase == RUNNING || phase == SUCCEEDED {
-                        pod.metadata.labels.as_ref().map(|labels| (phase, labels))
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            },
-            _ => None,
-        })
+        .filter(|pod| is_model_pod(model, pod))
+        .flat_map(|pod| pod.status.as_ref().and_then(|status| status.phase.as_ref()))
+        .filter(|phase| *phase == RUNNING || *phase == SUCCEEDED)
         .collect();
+    if pods_phases.is_empty() {
+        return None;
+    }
+    info!(
+        "Found pods in phases {:?} for the model '{:?}'",
+        pods_phases, model.metadata.name
+    );
 
-    //Update models statuses according to phases of pods which is related to each of this model
-    for model in actual_models {
-        let model_name = match &model.metadata.name {
-            Some(model_name) => model_name,
-            None => {
-                warn!("Pod's field .metadata.name is empty");
-                continue;
-            }
-        };
-        let new_model_status = match &model.status {
-            Some(status) => {
-                let pods_labels = &actual_pods_labels;
-                let pods_phases: Vec<_> = pods_labels
-                    .into_iter()
-                    .filter(|(_, labels)| match &model.metadata.labels {
-                        Some(model_labels) => POD_MATCH_LABELS
-                            .iter()
-                            .all(|&label_name| model_labels.get(label_name) == labels.get(label_name)),
-                        None => false,
-                    })
-                    .map(|(phase, _)| phase)
-                    .collect();
-                if pods_phases.len() > 0 {
-                    info!("Found pods in phases {:?} for the model '{}'", pods_phases, model_name);
-                    let mut new_status = status.clone();
-                    let mut new_phase = new_status.phase.clone();
-                    if pods_phases.iter().any(|phase| *phase == SUCCEEDED) {
-                        new_phase = ModelPhase::Succeeded;
-                    } else if pods_phases.iter().any(|phase| *phase == RUNNING) {
-                        new_phase = ModelPhase::InProgress;
-                    }
-                    if new_phase != new_status.phase {
-                        new_status.phase = new_phase;
-                        Some(new_status)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }
-            None => None,
-        };
-        if let Some(new_status) = new_model_status {
-            update_model_status(model_api, &model, &new_status).await;
-        }
+    let mut new_status = status.clone();
+    if pods_phases.iter().any(|phase| *phase == SUCCEEDED) {
+        new_status.phase = ModelPhase::Succeeded;
+    } else if pods_phases.iter().any(|phase| *phase == RUNNING) {
+        new_status.phase = ModelPhase::InProgress;
+    }
+    if new_status.phase == status.phase {
+        return None;
     }
+    new_status.update_conditions(model.metadata.generation);
+    update_model_status(model_api, model, &new_status, recorder).await
 }
@@ -1,9 +1,11 @@
 use crate::{
-    utils::{env_var, object_to_owner_reference},
+    crd::gordo::gordo::{DeployEnvValue, DeployPodTemplate},
+    crd::job::{DEPLOY_CONTAINER_NAME, GORDO_PROJECT_NAME_LABEL},
+    utils::object_to_owner_reference,
     Config, Gordo,
 };
 use k8s_openapi::api::batch::v1::{Job, JobSpec};
-use k8s_openapi::api::core::v1::{Container, EnvVar, PodSpec, PodTemplateSpec, ResourceRequirements};
+use k8s_openapi::api::core::v1::{Container, EnvFromSource, EnvVar, PodSpec, PodTemplateSpec, ResourceRequirements};
 use k8s_openapi::api::core::v1::{EmptyDirVolumeSource, SecurityContext, Volume, VolumeMount};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta as OpenApiObjectMeta;
@@ -14,13 +16,17 @@ use std::iter::FromIterator;
 
 // TODO builder
 
+pub const DEPLOY_JOB_NAME_PREFIX: &str = "gordo-dpl-";
+/// Maximum length of a resource name or a label value
+pub const MAX_NAME_LENGTH: usize = 63;
+
 /// Generate a name which is no greater than 63 chars in length
 /// always keeping the `prefix` and as much of `suffix` as possible, favoring its ending.
 pub fn deploy_job_name(prefix: &str, suffix: &str) -> String {
     let suffix = suffix
         .chars()
         .rev()
-        .take(63 - prefix.len())
+        .take(MAX_NAME_LENGTH - prefix.len())
         .collect::<Vec<char>>()
         .iter()
         .rev()
@@ -45,15 +51,9 @@ fn deploy_image(gordo: &Gordo, config: &Config) -> String {
     }
 }
 
-fn deploy_container(gordo: &Gordo, environment: Vec<EnvVar>, config: &Config) -> Container {
-    let mut container = Container::default();
-    container.name = "gordo-deploy".to_string();
-    let deploy_image = deploy_image(gordo, config);
-    container.image = Some(format!("{}:{}", deploy_image, &gordo.spec.deploy_version));
-    container.command = Some(vec!["bash".to_string(), "./run_workflow_and_argo.sh".to_string()]);
-    container.image_pull_policy = Some("Always".to_string());
-    container.env = Some(environment);
-    container.resources = Some(ResourceRequirements {
+/// Resources of the gordo-deploy container if neither the Gordo nor the controller set them
+fn default_deploy_resources() -> ResourceRequirements {
+    ResourceRequirements {
         limits: Some(BTreeMap::from_iter(
             vec![
                 ("memory".to_owned(), Quantity("1000Mi".to_owned())),
@@ -68,7 +68,34 @@ fn deploy_container(gordo: &Gordo, environment: Vec<EnvVar>, config: &Config) ->
             ]
             .into_iter(),
         )),
-    });
+    }
+}
+
+fn deploy_container(
+    gordo: &Gordo,
+    environment: Vec<EnvVar>,
+    env_from: Vec<EnvFromSource>,
+    pod_template: &DeployPodTemplate,
+    config: &Config,
+) -> Container {
+    let deploy_image = deploy_image(gordo, config);
+    let mut container = Container {
+        name: DEPLOY_CONTAINER_NAME.to_string(),
+        image: Some(format!("{}:{}", deploy_image, &gordo.spec.deploy_version)),
+        ..Container::default()
+    };
+    container.command = Some(vec!["bash".to_string(), "./run_workflow_and_argo.sh".to_string()]);
+    container.image_pull_policy = Some(
+        pod_template
+            .image_pull_policy
+            .clone()
+            .unwrap_or_else(|| "Always".to_string()),
+    );
+    container.env = Some(environment);
+    if !env_from.is_empty() {
+        container.env_from = Some(env_from);
+    }
+    container.resources = Some(pod_template.resources.clone().unwrap_or_else(default_deploy_resources));
     let mut security_context = SecurityContext::default();
     security_context.run_as_non_root = Some(true);
     if config.deploy_job_ro_fs {
@@ -83,9 +110,14 @@ fn deploy_container(gordo: &Gordo, environment: Vec<EnvVar>, config: &Config) ->
     container
 }
 
-fn deploy_pod_spec(containers: Vec<Container>, config: &Config) -> PodSpec {
+fn deploy_pod_spec(containers: Vec<Container>, pod_template: &DeployPodTemplate, config: &Config) -> PodSpec {
     let mut pod_spec = PodSpec::default();
     pod_spec.containers = containers;
+    pod_spec.node_selector = pod_template.node_selector.clone();
+    pod_spec.tolerations = pod_template.tolerations.clone();
+    pod_spec.affinity = pod_template.affinity.clone();
+    pod_spec.priority_class_name = pod_template.priority_class_name.clone();
+    pod_spec.image_pull_secrets = pod_template.image_pull_secrets.clone();
     pod_spec.restart_policy = Some("Never".to_string());
     if config.deploy_job_ro_fs {
         pod_spec.volumes = Some(vec![Volume {
@@ -114,7 +146,7 @@ fn deploy_labels(gordo: &Gordo, resources_labels: &Option<BTreeMap<String, Strin
             return labels;
         }
     };
-    labels.insert("gordoProjectName".to_owned(), name.to_string());
+    labels.insert(GORDO_PROJECT_NAME_LABEL.to_owned(), name.to_string());
     if let Some(additional_labels) = resources_labels {
         for (label, value) in additional_labels {
             labels.insert(label.to_owned(), value.to_owned());
@@ -123,7 +155,7 @@ fn deploy_labels(gordo: &Gordo, resources_labels: &Option<BTreeMap<String, Strin
     labels
 }
 
-pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
+pub fn create_deploy_job(gordo: &Gordo, config: &Config, project_revision: &str) -> Option<Job> {
     // Create the job name.
     let name = match &gordo.metadata.name {
         Some(name) => name,
@@ -133,7 +165,7 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
         }
     };
     let job_name_suffix = format!("{}-{}", name, &gordo.metadata.generation.unwrap_or(0));
-    let job_name = deploy_job_name("gordo-dpl-", &job_name_suffix);
+    let job_name = deploy_job_name(DEPLOY_JOB_NAME_PREFIX, &job_name_suffix);
 
     info!("Creating job \"{}\" for Gordo \"{}\"", job_name, name);
 
@@ -146,7 +178,6 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
         }
     };
     let owner_ref_as_string = serde_json::to_string(&vec![owner_references.clone()]).unwrap();
-    let project_revision = chrono::Utc::now().timestamp_millis().to_string();
     let mut debug_show_workflow = "";
     if gordo.spec.debug_show_workflow.unwrap_or(false) {
         debug_show_workflow = "true"
@@ -155,7 +186,7 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
     // TODO Handle possible panic here
     let resources_labels = config.get_resources_labels_json().unwrap();
 
-    let mut initial_environment: BTreeMap<String, String> = BTreeMap::new();
+    let mut initial_environment: BTreeMap<String, DeployEnvValue> = BTreeMap::new();
 
     for (key, value) in config.workflow_generator_envs.iter() {
         initial_environment.insert(key.into(), value.into());
@@ -164,20 +195,20 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
     // Build up the gordo-deploy environment variables
     initial_environment.insert("GORDO_NAME".into(), name.into());
     initial_environment.insert("ARGO_SUBMIT".into(), "true".into());
-    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_NAME".into(), name.clone());
-    initial_environment.insert("WORKFLOW_GENERATOR_OWNER_REFERENCES".into(), owner_ref_as_string);
-    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_REVISION".into(), project_revision.clone());
+    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_NAME".into(), name.into());
+    initial_environment.insert("WORKFLOW_GENERATOR_OWNER_REFERENCES".into(), owner_ref_as_string.into());
+    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_REVISION".into(), project_revision.into());
     // TODO: Backward compat. Until all have moved >=0.47.0 of gordo-components
-    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_VERSION".into(), project_revision);
+    initial_environment.insert("WORKFLOW_GENERATOR_PROJECT_VERSION".into(), project_revision.into());
     initial_environment.insert(
         "WORKFLOW_GENERATOR_DOCKER_REGISTRY".into(),
-        config.docker_registry.clone(),
+        config.docker_registry.clone().into(),
     );
     initial_environment.insert(
         "WORKFLOW_GENERATOR_GORDO_VERSION".into(),
-        gordo.spec.deploy_version.clone(),
+        gordo.spec.deploy_version.clone().into(),
     );
-    initial_environment.insert("WORKFLOW_GENERATOR_RESOURCE_LABELS".into(), resources_labels);
+    initial_environment.insert("WORKFLOW_GENERATOR_RESOURCE_LABELS".into(), resources_labels.into());
     initial_environment.insert("DEBUG_SHOW_WORKFLOW".into(), debug_show_workflow.into());
 
     // As long as we calling env_config.validate() method in the main function
@@ -186,7 +217,7 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
 
     if let Some(deploy_environment) = default_deploy_environment {
         for (key, value) in deploy_environment.iter() {
-            initial_environment.insert(key.into(), value.into());
+            initial_environment.insert(key.into(), value.clone());
         }
     }
 
@@ -196,7 +227,7 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
 
     initial_environment.insert(
         "ARGO_VERSION_NUMBER".into(),
-        config.argo_version_number.map_or("".into(), |v| v.to_string()),
+        config.argo_version_number.map_or("".into(), |v| v.to_string().into()),
     );
 
     let resources_labels = &config.resources_labels;
@@ -204,17 +235,33 @@ pub fn create_deploy_job(gordo: &Gordo, config: &Config) -> Option<Job> {
     // push in any that were supplied by the Gordo.spec.gordo_environment mapping
     gordo.spec.deploy_environment.as_ref().map(|env| {
         env.iter().for_each(|(key, value)| {
-            initial_environment.insert(key.into(), value.into());
+            initial_environment.insert(key.into(), value.clone());
         })
     });
 
     let mut environment: Vec<EnvVar> = vec![];
     initial_environment.iter().for_each(|(key, value)| {
-        environment.push(env_var(key, value));
+        environment.push(value.env_var(key));
     });
 
-    let container = deploy_container(&gordo, environment, config);
-    let pod_spec = deploy_pod_spec(vec![container], config);
+    // Pod overrides of the Gordo take precedence over the controller's
+    let pod_template = match &gordo.spec.deploy_pod_template {
+        Some(pod_template) => pod_template.merge(&config.deploy_pod_template),
+        None => config.deploy_pod_template.clone(),
+    };
+    // Secrets and ConfigMaps of the Gordo come last, so their keys take precedence over the controller's
+    let gordo_env_from = gordo.spec.deploy_env_from.clone().unwrap_or_default();
+    let mut env_from: Vec<EnvFromSource> = config
+        .default_deploy_env_from
+        .iter()
+        .flatten()
+        .filter(|source| !gordo_env_from.contains(source))
+        .cloned()
+        .collect();
+    env_from.extend(gordo_env_from);
+
+    let container = deploy_container(gordo, environment, env_from, &pod_template, config);
+    let pod_spec = deploy_pod_spec(vec![container], &pod_template, config);
     let spec_metadata = deploy_pod_spec_metadata(&job_name, resources_labels);
 
     let mut metadata = ObjectMeta::default();
@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,8 +9,26 @@ pub enum Error {
     #[error("Kube API Error: {0}")]
     KubeError(#[source] kube::Error),
 
-    #[error("{0} is empty")]
-    NotFound(&'static str),
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("{0} is not ready")]
+    NotReady(&'static str),
+
+    /// Request refused in the current state of the object, with the reason
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("YAML Error: {0}")]
+    YamlError(#[source] serde_yaml::Error),
+
+    /// Failed reconcile, retried after `retry_in` or only once the object changes if `None`
+    #[error("{source}")]
+    Backoff {
+        #[source]
+        source: Box<Error>,
+        retry_in: Option<Duration>,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -21,11 +40,33 @@ pub enum ConfigError {
 }
 
 impl Error {
-    pub fn error_name(self) -> &'static str {
+    pub fn error_name(&self) -> &'static str {
         match self {
             Error::MissingKey(_) => "missing_key",
             Error::KubeError(_) => "kube_error",
             Error::NotFound(_) => "not_found",
+            Error::NotReady(_) => "not_ready",
+            Error::Conflict(_) => "conflict",
+            Error::YamlError(_) => "yaml_error",
+            Error::Backoff { source, .. } => source.error_name(),
+        }
+    }
+
+    /// `true` if the API server doesn't know the object anymore
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::KubeError(kube::Error::Api(response)) => response.code == 404,
+            Error::Backoff { source, .. } => source.is_not_found(),
+            _ => false,
+        }
+    }
+
+    /// `true` if retrying can't succeed until the object changes
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Error::MissingKey(_) | Error::YamlError(_) | Error::Conflict(_) => true,
+            Error::KubeError(_) | Error::NotFound(_) | Error::NotReady(_) => false,
+            Error::Backoff { source, .. } => source.is_permanent(),
         }
     }
 }
@@ -0,0 +1,100 @@
+use crate::crd::metrics::KUBE_ERRORS;
+use k8s_openapi::api::core::v1::{Event, EventSource};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
+use kube::api::{Api, PostParams, Resource};
+use kube::client::Client;
+use log::{debug, error};
+
+pub const REPORTING_COMPONENT: &str = "gordo-controller";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventType {
+    Normal,
+    Warning,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Normal => "Normal",
+            EventType::Warning => "Warning",
+        }
+    }
+}
+
+/// Publishes `core/v1` Events about the resources handled by the controller,
+/// shown by `kubectl describe`
+#[derive(Clone)]
+pub struct Recorder {
+    client: Client,
+    instance: Option<String>,
+}
+
+/// Build an Event regarding the `resource`
+pub fn new_event<K: Resource<DynamicType = ()>>(
+    resource: &K,
+    type_: EventType,
+    reason: &str,
+    message: &str,
+    instance: Option<&str>,
+) -> Event {
+    let involved_object = resource.object_ref(&());
+    let now = Time(chrono::Utc::now());
+    Event {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}.", involved_object.name.clone().unwrap_or_default())),
+            namespace: involved_object.namespace.clone(),
+            ..ObjectMeta::default()
+        },
+        involved_object,
+        type_: Some(type_.as_str().to_string()),
+        reason: Some(reason.to_string()),
+        message: Some(message.to_string()),
+        count: Some(1),
+        first_timestamp: Some(now.clone()),
+        last_timestamp: Some(now),
+        source: Some(EventSource {
+            component: Some(REPORTING_COMPONENT.to_string()),
+            host: None,
+        }),
+        reporting_component: Some(REPORTING_COMPONENT.to_string()),
+        reporting_instance: instance.map(ToOwned::to_owned),
+        ..Event::default()
+    }
+}
+
+impl Recorder {
+    pub fn new(client: Client, instance: Option<String>) -> Self {
+        Recorder { client, instance }
+    }
+
+    /// Publish an Event regarding the `resource`. Failures are only logged, events are best effort
+    pub async fn publish<K: Resource<DynamicType = ()>>(
+        &self,
+        resource: &K,
+        type_: EventType,
+        reason: &str,
+        message: &str,
+    ) -> () {
+        let event = new_event(resource, type_, reason, message, self.instance.as_deref());
+        let namespace = match &event.metadata.namespace {
+            Some(namespace) => namespace.clone(),
+            None => {
+                error!("Unable to publish event '{}' for a resource without namespace", reason);
+                return;
+            }
+        };
+        debug!(
+            "Publishing {} event '{}' for {:?}: {}",
+            type_.as_str(),
+            reason,
+            event.involved_object.name,
+            message
+        );
+        let events: Api<Event> = Api::namespaced(self.client.clone(), &namespace);
+        if let Err(err) = events.create(&PostParams::default(), &event).await {
+            error!("Failed to publish event '{}' - error: {:?}", reason, err);
+            KUBE_ERRORS.with_label_values(&["publish_event", "event"]).inc_by(1);
+        }
+    }
+}
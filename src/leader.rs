@@ -0,0 +1,225 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+use kube::api::{Api, PostParams};
+use kube::client::Client;
+use log::{error, info, warn};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+/// How long a lease stays valid without being renewed
+pub const LEASE_DURATION_SECONDS: i32 = 15;
+/// How long the leader keeps running without renewing its lease, shorter than `LEASE_DURATION_SECONDS`
+pub const RENEW_DEADLINE_SECONDS: i64 = 10;
+/// How often the leader renews its lease, and followers try to acquire it
+pub const RENEW_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Role of this replica, shared with the HTTP API
+#[derive(Clone, Default, Debug)]
+pub struct LeaderState(Arc<AtomicBool>);
+
+impl LeaderState {
+    pub fn is_leader(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn set_leader(&self, is_leader: bool) {
+        self.0.store(is_leader, Ordering::SeqCst)
+    }
+
+    pub fn role(&self) -> &'static str {
+        if self.is_leader() {
+            "leader"
+        } else {
+            "follower"
+        }
+    }
+}
+
+/// `true` if the lease is not held, or its holder didn't renew it in time
+pub fn is_lease_expired(spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+    match (&spec.holder_identity, &spec.renew_time) {
+        (Some(holder), Some(renew_time)) if !holder.is_empty() => {
+            let lease_duration = spec.lease_duration_seconds.unwrap_or(LEASE_DURATION_SECONDS);
+            renew_time.0 + ChronoDuration::seconds(lease_duration as i64) < now
+        }
+        _ => true,
+    }
+}
+
+/// The lease spec after `identity` acquired or renewed it, `None` if it's held by another replica
+pub fn acquire_lease_spec(spec: &LeaseSpec, identity: &str, now: DateTime<Utc>) -> Option<LeaseSpec> {
+    let is_holder = spec.holder_identity.as_deref() == Some(identity);
+    if !is_holder && !is_lease_expired(spec, now) {
+        return None;
+    }
+    let mut new_spec = spec.clone();
+    if !is_holder {
+        new_spec.holder_identity = Some(identity.to_string());
+        new_spec.acquire_time = Some(MicroTime(now));
+        new_spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+    }
+    new_spec.renew_time = Some(MicroTime(now));
+    new_spec.lease_duration_seconds = Some(LEASE_DURATION_SECONDS);
+    Some(new_spec)
+}
+
+/// Lease based leader election, only the leader runs the controller
+pub struct LeaderElection {
+    api: Api<Lease>,
+    lease_name: String,
+    identity: String,
+    state: LeaderState,
+}
+
+impl LeaderElection {
+    pub fn new(client: Client, namespace: &str, lease_name: &str, identity: &str) -> Self {
+        LeaderElection {
+            api: Api::namespaced(client, namespace),
+            lease_name: lease_name.to_string(),
+            identity: identity.to_string(),
+            state: LeaderState::default(),
+        }
+    }
+
+    pub fn state(&self) -> LeaderState {
+        self.state.clone()
+    }
+
+    /// Create, take over or renew the lease. Returns `true` if this replica holds it
+    async fn try_acquire_or_renew(&self) -> kube::Result<bool> {
+        let now = Utc::now();
+        match self.api.get_opt(&self.lease_name).await? {
+            Some(lease) => {
+                let spec = lease.spec.clone().unwrap_or_default();
+                let new_spec = match acquire_lease_spec(&spec, &self.identity, now) {
+                    Some(new_spec) => new_spec,
+                    None => return Ok(false),
+                };
+                if spec.holder_identity != new_spec.holder_identity {
+                    info!(
+                        "Taking over lease '{}' from {:?}",
+                        self.lease_name, spec.holder_identity
+                    );
+                }
+                // `resourceVersion` is kept, so only one replica can win a concurrent update
+                let new_lease = Lease {
+                    spec: Some(new_spec),
+                    ..lease
+                };
+                match self
+                    .api
+                    .replace(&self.lease_name, &PostParams::default(), &new_lease)
+                    .await
+                {
+                    Ok(_) => Ok(true),
+                    Err(kube::Error::Api(err)) if err.code == 409 => Ok(false),
+                    Err(err) => Err(err),
+                }
+            }
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.lease_name.clone()),
+                        ..ObjectMeta::default()
+                    },
+                    spec: acquire_lease_spec(&LeaseSpec::default(), &self.identity, now),
+                };
+                match self.api.create(&PostParams::default(), &lease).await {
+                    Ok(_) => Ok(true),
+                    Err(kube::Error::Api(err)) if err.code == 409 => Ok(false),
+                    Err(err) => Err(err),
+                }
+            }
+        }
+    }
+
+    /// Wait until this replica becomes the leader
+    pub async fn acquire(&self) {
+        info!("Waiting for lease '{}' as '{}'", self.lease_name, self.identity);
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    info!("Acquired lease '{}', running as leader", self.lease_name);
+                    self.state.set_leader(true);
+                    return;
+                }
+                Ok(false) => (),
+                Err(err) => error!("Failed to acquire lease '{}' - error: {:?}", self.lease_name, err),
+            }
+            sleep(RENEW_INTERVAL).await;
+        }
+    }
+
+    /// Keep renewing the lease, returns once the leadership is lost
+    pub async fn renew(&self) {
+        let mut last_renew = Utc::now();
+        loop {
+            sleep(RENEW_INTERVAL).await;
+            match self.try_acquire_or_renew().await {
+                Ok(true) => last_renew = Utc::now(),
+                Ok(false) => {
+                    warn!("Lease '{}' is held by another replica", self.lease_name);
+                    break;
+                }
+                Err(err) => {
+                    error!("Failed to renew lease '{}' - error: {:?}", self.lease_name, err);
+                    // Step down before another replica is allowed to take the lease over
+                    if last_renew + ChronoDuration::seconds(RENEW_DEADLINE_SECONDS) < Utc::now() {
+                        break;
+                    }
+                }
+            }
+        }
+        warn!("Lost lease '{}'", self.lease_name);
+        self.state.set_leader(false);
+    }
+
+    /// Run the controller built by `run` whenever this replica holds the lease. It is dropped as soon as the
+    /// leadership is lost, and the replica waits for the lease again. Returns once the controller drained
+    pub async fn run_while_leader<F, Fut>(&self, run: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            self.acquire().await;
+            tokio::select! {
+                _ = run() => return,
+                _ = self.renew() => (),
+            }
+        }
+    }
+
+    /// Give up the lease if this replica holds it, so another one takes over without waiting for it to expire
+    pub async fn release(&self) {
+        self.state.set_leader(false);
+        let lease = match self.api.get_opt(&self.lease_name).await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => return,
+            Err(err) => {
+                error!("Failed to release lease '{}' - error: {:?}", self.lease_name, err);
+                return;
+            }
+        };
+        let mut spec = lease.spec.clone().unwrap_or_default();
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return;
+        }
+        spec.holder_identity = None;
+        let new_lease = Lease {
+            spec: Some(spec),
+            ..lease
+        };
+        match self
+            .api
+            .replace(&self.lease_name, &PostParams::default(), &new_lease)
+            .await
+        {
+            Ok(_) => info!("Released lease '{}'", self.lease_name),
+            Err(err) => error!("Failed to release lease '{}' - error: {:?}", self.lease_name, err),
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use crate::crd::metrics::{RECONCILE_GORDO_COUNT, RECONCILE_GORDO_ERROR, RECONCILE_GORDO_SUCCEDED};
+use crate::crd::metrics::{
+    observe_reconcile, remove_gordo_metrics, update_gordo_metrics, RECONCILE_GORDO_COUNT, RECONCILE_GORDO_ERROR,
+    RECONCILE_GORDO_SUCCEDED, RECONCILE_MODEL_COUNT, RECONCILE_MODEL_ERROR, RECONCILE_MODEL_SUCCEDED,
+};
 use crate::errors::ConfigError;
-use futures::StreamExt;
-use k8s_openapi::api::core::v1::Pod;
+use futures::{future::join_all, FutureExt, StreamExt};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{EnvFromSource, Pod};
 use kube::runtime::controller::{Action, Context, Controller};
+use kube::runtime::reflector::ObjectRef;
 use kube::{
-    api::{Api, ListParams},
+    api::{Api, ListParams, Resource},
     client::Client,
 };
 use log::{debug, info, warn};
@@ -12,23 +17,49 @@ use serde::Deserialize;
 use serde_json;
 use std::result::Result;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::time::Duration;
 
+pub mod backoff;
+pub mod cache;
 pub mod crd;
 pub mod deploy_job;
 pub mod errors;
+pub mod events;
+pub mod leader;
+pub mod logging;
+pub mod redact;
+pub mod telemetry;
 pub mod utils;
 pub mod views;
+pub mod webhook;
 
 use crate::crd::{
-    argo::{monitor_wf, Workflow},
-    gordo::{handle_gordo_state, Gordo},
-    model::{monitor_models, Model},
-    pod::monitor_pods,
+    argo::{is_model_workflow, monitor_wf, Workflow},
+    gordo::cleanup::{add_cleanup_finalizer, cleanup_gordo, has_cleanup_finalizer},
+    gordo::retention::collect_superseded_revisions,
+    gordo::rollback::handle_gordo_rollback,
+    gordo::suspend::{handle_gordo_suspend, has_suspended_condition, is_suspended},
+    gordo::{
+        gordo::{patch_gordo_backoff, BackoffStatus, DeployEnvValue, DeployPodTemplate, RevisionRetention},
+        handle_gordo_state, Gordo,
+    },
+    job::{monitor_deploy_job, GORDO_PROJECT_NAME_LABEL},
+    model::{init_model_status, monitor_models, retry::retry_failed_model, Model},
+    pod::{is_model_pod, monitor_pods},
 };
+use backoff::{BackoffConfig, BackoffEntry, Backoffs};
+use cache::{Cache, PROJECT_NAME_LABEL};
+use chrono::Utc;
 pub use deploy_job::create_deploy_job;
 use errors::Error;
+use events::Recorder;
+use logging::LogFormat;
+use redact::{Redactor, DEFAULT_REDACT_PATTERNS};
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use telemetry::{reconcile_gordo_span, reconcile_model_span};
+use tracing::Instrument;
 
 fn default_deploy_repository() -> String {
     "".to_string()
@@ -46,6 +77,54 @@ fn default_deploy_ro_fs() -> bool {
     false
 }
 
+fn default_watch_namespaces() -> String {
+    "".to_string()
+}
+
+fn default_leader_election() -> bool {
+    false
+}
+
+fn default_leader_election_lease_name() -> String {
+    String::from("gordo-controller")
+}
+
+fn default_webhook_port() -> u16 {
+    8443
+}
+
+fn default_reconcile_interval() -> u64 {
+    300
+}
+
+fn default_reconcile_backoff_base() -> u64 {
+    5
+}
+
+fn default_reconcile_backoff_max() -> u64 {
+    600
+}
+
+fn default_reconcile_backoff_jitter() -> f64 {
+    0.1
+}
+
+fn default_suspend_workflows() -> bool {
+    true
+}
+
+fn default_revision_history_limit() -> usize {
+    10
+}
+
+fn default_log_format() -> String {
+    "text".to_owned()
+}
+
+fn default_redact_patterns() -> String {
+    DEFAULT_REDACT_PATTERNS.to_owned()
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GordoEnvironmentConfig {
     pub deploy_image: String,
@@ -57,26 +136,91 @@ pub struct GordoEnvironmentConfig {
     pub server_host: String,
     pub docker_registry: String,
     pub default_deploy_environment: String,
+    #[serde(default)]
+    pub default_deploy_env_from: String,
     pub resources_labels: String,
     #[serde(default = "default_deploy_ro_fs")]
     pub deploy_job_ro_fs: bool,
     pub argo_service_account: Option<String>,
     pub argo_version_number: Option<String>,
+    #[serde(default = "default_watch_namespaces")]
+    pub watch_namespaces: String,
+    #[serde(default = "default_leader_election")]
+    pub leader_election: bool,
+    #[serde(default = "default_leader_election_lease_name")]
+    pub leader_election_lease_name: String,
+    pub leader_election_namespace: Option<String>,
+    #[serde(default = "default_webhook_port")]
+    pub webhook_port: u16,
+    pub webhook_tls_cert: Option<String>,
+    pub webhook_tls_key: Option<String>,
+    #[serde(default)]
+    pub deploy_pod_template: String,
+    #[serde(default = "default_reconcile_interval")]
+    pub reconcile_interval: u64,
+    #[serde(default = "default_reconcile_backoff_base")]
+    pub reconcile_backoff_base: u64,
+    #[serde(default = "default_reconcile_backoff_max")]
+    pub reconcile_backoff_max: u64,
+    #[serde(default = "default_reconcile_backoff_jitter")]
+    pub reconcile_backoff_jitter: f64,
+    #[serde(default = "default_suspend_workflows")]
+    pub suspend_workflows: bool,
+    #[serde(default)]
+    pub revision_retention: String,
+    #[serde(default = "default_revision_history_limit")]
+    pub revision_history_limit: usize,
+    #[serde(default = "default_log_format")]
+    pub log_format: String,
+    pub otlp_endpoint: Option<String>,
+    #[serde(default = "default_redact_patterns")]
+    pub redact_patterns: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Config {
     pub deploy_image: String,
     pub deploy_repository: String,
     pub server_port: u16,
     pub server_host: String,
     pub docker_registry: String,
-    pub default_deploy_environment: Option<HashMap<String, String>>,
+    /// Default `deploy-environment` of the Gordos, with literal values or `valueFrom` references
+    pub default_deploy_environment: Option<HashMap<String, DeployEnvValue>>,
+    /// Default `deploy-env-from` of the Gordos, Secrets and ConfigMaps exposed as environment variables
+    pub default_deploy_env_from: Option<Vec<EnvFromSource>>,
     pub resources_labels: Option<BTreeMap<String, String>>,
     pub deploy_job_ro_fs: bool,
     pub argo_service_account: Option<String>,
     pub argo_version_number: Option<u8>,
     pub workflow_generator_envs: Vec<(String, String)>,
+    /// Namespaces watched by the controller and served by the HTTP API, empty means cluster-wide
+    pub watch_namespaces: Vec<String>,
+    /// Only run the controller in the replica holding the Lease, the HTTP API is served by all of them
+    pub leader_election: bool,
+    pub leader_election_lease_name: String,
+    /// Namespace of the Lease, the controller's own namespace if empty
+    pub leader_election_namespace: Option<String>,
+    /// HTTPS port serving the admission webhooks, only if the TLS certificate and key are set
+    pub webhook_port: u16,
+    pub webhook_tls_cert: Option<String>,
+    pub webhook_tls_key: Option<String>,
+    /// Defaults of the gordo-deploy Job's pod, overridden by `deploy-pod-template` of each Gordo
+    pub deploy_pod_template: DeployPodTemplate,
+    /// Delay before reconciling an object again after it succeeded
+    pub reconcile_interval: Duration,
+    /// Retries of the objects which failed to reconcile
+    pub reconcile_backoff: BackoffConfig,
+    /// Also suspend the running workflows of the suspended Gordos
+    pub suspend_workflows: bool,
+    /// Superseded project revisions kept, overridden by `revision-retention` of each Gordo
+    pub revision_retention: RevisionRetention,
+    /// Number of deployed revisions kept in the history of each Gordo's status
+    pub revision_history_limit: usize,
+    pub log_format: LogFormat,
+    /// OTLP/HTTP collector the spans are exported to, not exported if empty
+    pub otlp_endpoint: Option<String>,
+    /// Masks the secrets of the logs, and of the `Debug` output of the config
+    pub redactor: Redactor,
 }
 
 impl Config {
@@ -95,11 +239,12 @@ impl Config {
         }
         let env_config: GordoEnvironmentConfig =
             envy::from_iter::<_, _>(other_envs.into_iter()).map_err(|err| ConfigError::Environment(err))?;
-        debug!("WORKFLOW_GENERATOR environments: {:?}", workflow_generator_envs);
-        debug!("Environment config: {:?}", &env_config);
-        let default_deploy_environment: Option<HashMap<String, String>> =
+        let default_deploy_environment: Option<HashMap<String, DeployEnvValue>> =
             Config::load_from_json(&env_config.default_deploy_environment)
                 .map_err(|err| ConfigError::Field("DEFAULT_DEPLOY_ENVIRONMENT", err))?;
+        let default_deploy_env_from: Option<Vec<EnvFromSource>> =
+            Config::load_from_json(&env_config.default_deploy_env_from)
+                .map_err(|err| ConfigError::Field("DEFAULT_DEPLOY_ENV_FROM", err))?;
         let resources_labels: Option<BTreeMap<String, String>> =
             Config::load_from_json(&env_config.resources_labels)
                 .map_err(|err| ConfigError::Field("RESOURCES_LABELS", err))?;
@@ -112,6 +257,26 @@ impl Config {
             }
             None => None,
         };
+        let watch_namespaces = Config::load_from_list(&env_config.watch_namespaces);
+        let deploy_pod_template: Option<DeployPodTemplate> = Config::load_from_json(&env_config.deploy_pod_template)
+            .map_err(|err| ConfigError::Field("DEPLOY_POD_TEMPLATE", err))?;
+        let revision_retention: Option<RevisionRetention> = Config::load_from_json(&env_config.revision_retention)
+            .map_err(|err| ConfigError::Field("REVISION_RETENTION", err))?;
+        let log_format: LogFormat = env_config
+            .log_format
+            .parse()
+            .map_err(|err| ConfigError::Field("LOG_FORMAT", err))?;
+        if !(0.0..=1.0).contains(&env_config.reconcile_backoff_jitter) {
+            return Err(ConfigError::Field(
+                "RECONCILE_BACKOFF_JITTER",
+                "must be between 0 and 1".to_string(),
+            ));
+        }
+        let reconcile_backoff = BackoffConfig {
+            base: Duration::from_secs(env_config.reconcile_backoff_base),
+            max: Duration::from_secs(env_config.reconcile_backoff_max),
+            jitter: env_config.reconcile_backoff_jitter,
+        };
         Ok(Config {
             deploy_image: env_config.deploy_image.clone(),
             deploy_repository: env_config.deploy_repository.clone(),
@@ -123,10 +288,42 @@ impl Config {
             argo_version_number: argo_version_number,
             workflow_generator_envs: workflow_generator_envs,
             default_deploy_environment,
+            default_deploy_env_from,
             resources_labels,
+            watch_namespaces,
+            leader_election: env_config.leader_election,
+            leader_election_lease_name: env_config.leader_election_lease_name,
+            leader_election_namespace: env_config.leader_election_namespace,
+            webhook_port: env_config.webhook_port,
+            webhook_tls_cert: env_config.webhook_tls_cert,
+            webhook_tls_key: env_config.webhook_tls_key,
+            deploy_pod_template: deploy_pod_template.unwrap_or_default(),
+            reconcile_interval: Duration::from_secs(env_config.reconcile_interval),
+            reconcile_backoff,
+            suspend_workflows: env_config.suspend_workflows,
+            revision_retention: revision_retention.unwrap_or_default(),
+            revision_history_limit: env_config.revision_history_limit,
+            log_format,
+            otlp_endpoint: env_config.otlp_endpoint.filter(|endpoint| !endpoint.is_empty()),
+            redactor: Redactor::new(&Config::load_from_list(&env_config.redact_patterns)),
         })
     }
 
+    /// Split a comma separated list, skipping empty items
+    pub fn load_from_list(list_value: &str) -> Vec<String> {
+        list_value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    /// `true` if resources in this namespace are handled by the controller
+    pub fn is_watched_namespace(&self, namespace: &str) -> bool {
+        self.watch_namespaces.is_empty() || self.watch_namespaces.iter().any(|ns| ns == namespace)
+    }
+
     pub fn load_from_json<'a, T>(json_value: &'a str) -> Result<Option<T>, String>
     where
         T: Deserialize<'a>,
@@ -161,21 +358,96 @@ impl Default for GordoEnvironmentConfig {
             server_host: "0.0.0.0".to_owned(),
             docker_registry: "docker.io".to_owned(),
             default_deploy_environment: "".to_owned(),
+            default_deploy_env_from: "".to_owned(),
             resources_labels: "".to_owned(),
             deploy_job_ro_fs: false,
             argo_service_account: None,
             argo_version_number: None,
+            watch_namespaces: "".to_owned(),
+            leader_election: false,
+            leader_election_lease_name: "gordo-controller".to_owned(),
+            leader_election_namespace: None,
+            webhook_port: 8443,
+            webhook_tls_cert: None,
+            webhook_tls_key: None,
+            deploy_pod_template: "".to_owned(),
+            reconcile_interval: 300,
+            reconcile_backoff_base: 5,
+            reconcile_backoff_max: 600,
+            reconcile_backoff_jitter: 0.1,
+            suspend_workflows: true,
+            revision_retention: "".to_owned(),
+            revision_history_limit: 10,
+            log_format: "text".to_owned(),
+            otlp_endpoint: None,
+            redact_patterns: DEFAULT_REDACT_PATTERNS.to_owned(),
         }
     }
 }
 
+// The deploy environment and the workflow generator variables may hold secrets, which are masked
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default_deploy_environment = self.default_deploy_environment.as_ref().map(|environment| {
+            environment
+                .iter()
+                .map(|(name, value)| match value {
+                    DeployEnvValue::Value(value) => (
+                        name,
+                        DeployEnvValue::Value(self.redactor.redact(name, value).to_string()),
+                    ),
+                    value_from => (name, value_from.clone()),
+                })
+                .collect::<BTreeMap<_, _>>()
+        });
+        let workflow_generator_envs: Vec<(&String, &str)> = self
+            .workflow_generator_envs
+            .iter()
+            .map(|(name, value)| (name, self.redactor.redact(name, value)))
+            .collect();
+        f.debug_struct("Config")
+            .field("deploy_image", &self.deploy_image)
+            .field("deploy_repository", &self.deploy_repository)
+            .field("server_port", &self.server_port)
+            .field("server_host", &self.server_host)
+            .field("docker_registry", &self.docker_registry)
+            .field("default_deploy_environment", &default_deploy_environment)
+            .field("default_deploy_env_from", &self.default_deploy_env_from)
+            .field("resources_labels", &self.resources_labels)
+            .field("deploy_job_ro_fs", &self.deploy_job_ro_fs)
+            .field("argo_service_account", &self.argo_service_account)
+            .field("argo_version_number", &self.argo_version_number)
+            .field("workflow_generator_envs", &workflow_generator_envs)
+            .field("watch_namespaces", &self.watch_namespaces)
+            .field("leader_election", &self.leader_election)
+            .field("leader_election_lease_name", &self.leader_election_lease_name)
+            .field("leader_election_namespace", &self.leader_election_namespace)
+            .field("webhook_port", &self.webhook_port)
+            .field("webhook_tls_cert", &self.webhook_tls_cert)
+            .field("webhook_tls_key", &self.webhook_tls_key)
+            .field("deploy_pod_template", &self.deploy_pod_template)
+            .field("reconcile_interval", &self.reconcile_interval)
+            .field("reconcile_backoff", &self.reconcile_backoff)
+            .field("suspend_workflows", &self.suspend_workflows)
+            .field("revision_retention", &self.revision_retention)
+            .field("revision_history_limit", &self.revision_history_limit)
+            .field("log_format", &self.log_format)
+            .field("otlp_endpoint", &self.otlp_endpoint)
+            .field("redactor", &self.redactor)
+            .finish()
+    }
+}
+
 struct Data {
     client: Client,
     config: Config,
+    recorder: Recorder,
+    cache: Cache,
+    backoffs: Backoffs,
 }
 
 #[warn(unused_variables)]
-async fn reconcile_gordo(gordo: Arc<Gordo>, ctx: Context<Data>) -> Result<Action, Error> {
+async fn try_reconcile_gordo(gordo: Arc<Gordo>, ctx: Context<Data>) -> Result<Action, Error> {
     let namespace = gordo
         .metadata
         .namespace
@@ -191,32 +463,34 @@ async fn reconcile_gordo(gordo: Arc<Gordo>, ctx: Context<Data>) -> Result<Action
 
     let client = ctx.get_ref().client.clone();
     let config = ctx.get_ref().config.clone();
+    let recorder = &ctx.get_ref().recorder;
+    let cache = &ctx.get_ref().cache;
+
+    if !cache.is_ready() {
+        debug!("Cache is not ready yet, requeue gordo {:?}", gordo_name);
+        return Ok(Action::requeue(Duration::from_secs(5)));
+    }
 
     let gordo_api: Api<Gordo> = Api::namespaced(client.clone(), namespace);
     info!("Reconcile gordo: {:?}, namespace: {:?}", gordo_name, namespace);
-    let model_labels = format!("applications.gordo.equinor.com/project-name={}", gordo_name);
-    let lp = ListParams::default().labels(&model_labels);
 
-    handle_gordo_state(&gordo, &client, &gordo_api, namespace, &config)
-        .await
-        .map_err(|e| Error::KubeError(e))?;
+    if gordo.metadata.deletion_timestamp.is_some() {
+        remove_gordo_metrics(gordo_name, namespace);
+        let action = cleanup_gordo(&gordo, &client, &gordo_api, namespace, recorder).await?;
+        // The Models are deleted with the Gordo
+        for model in cache.models.by_project(namespace, gordo_name) {
+            ctx.get_ref().backoffs.forget(&ObjectRef::from_obj(&model).to_string());
+        }
+        return Ok(action);
+    }
 
-    let model_api: Api<Model> = Api::namespaced(client.clone(), namespace);
-    let models_obj_list = model_api.list(&lp).await.map_err(Error::KubeError)?;
-    let models: Vec<_> = models_obj_list.into_iter().collect();
-    let names = utils::resource_names(&models);
-    let current_gordo = (*gordo).clone();
-    debug!(
-        "Reconcile {} {}{}",
-        models.len(),
-        utils::plural_str(models.len(), "models", Some(": ".to_string())),
-        names
-    );
-    monitor_models(&model_api, &gordo_api, &models, &vec![current_gordo]).await;
+    // Each step works on the latest status of the Gordo, so it doesn't overwrite the previous one's
+    let mut gordo = (*gordo).clone();
+    if !has_cleanup_finalizer(&gordo) {
+        gordo = add_cleanup_finalizer(&gordo_api, &gordo).await?;
+    }
 
-    let workflow_api: Api<Workflow> = Api::namespaced(client.clone(), namespace);
-    let workflows_obj_list = workflow_api.list(&lp).await.map_err(Error::KubeError)?;
-    let workflows: Vec<_> = workflows_obj_list.into_iter().collect();
+    let workflows = cache.workflows.by_project(namespace, gordo_name);
     let names = utils::resource_names(&workflows);
     debug!(
         "Reconcile {} {}{}",
@@ -225,50 +499,349 @@ async fn reconcile_gordo(gordo: Arc<Gordo>, ctx: Context<Data>) -> Result<Action
         names
     );
 
-    let pod_api: Api<Pod> = Api::namespaced(client.clone(), namespace);
-    let pod_obj_list = pod_api.list(&lp).await.map_err(Error::KubeError)?;
-    let pods: Vec<_> = pod_obj_list.into_iter().collect();
-    let names = utils::resource_names(&pods);
+    if let Some(new_gordo) =
+        handle_gordo_suspend(&gordo, &client, &gordo_api, namespace, &workflows, &config, recorder).await?
+    {
+        gordo = new_gordo;
+    }
+    if is_suspended(&gordo) {
+        info!("Gordo {:?} is suspended, waiting for it to be resumed", gordo_name);
+        return Ok(Action::await_change());
+    }
+
+    if let Some(new_gordo) = handle_gordo_state(&gordo, &client, &gordo_api, namespace, &config, recorder)
+        .await
+        .map_err(Error::KubeError)?
+    {
+        gordo = new_gordo;
+    }
+
+    let models = cache.models.by_project(namespace, gordo_name);
+    let names = utils::resource_names(&models);
     debug!(
         "Reconcile {} {}{}",
-        pods.len(),
-        utils::plural_str(pods.len(), "pods", Some(": ".to_string())),
+        models.len(),
+        utils::plural_str(models.len(), "models", Some(": ".to_string())),
         names
     );
 
-    monitor_wf(&model_api, &workflows, &models, &pods).await;
-    monitor_pods(&model_api, &models, &pods).await;
+    if let Some(new_gordo) = handle_gordo_rollback(&gordo, &gordo_api, &models, recorder).await? {
+        gordo = new_gordo;
+    }
+    if let Some(new_gordo) = monitor_models(&gordo_api, &models, &workflows, &gordo).await {
+        gordo = new_gordo;
+    }
+    update_gordo_metrics(&gordo, &models);
+    collect_superseded_revisions(&gordo, &client, namespace, &models, &workflows, &config, recorder).await?;
+
+    let pod_api: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let job_api: Api<Job> = Api::namespaced(client.clone(), namespace);
+    monitor_deploy_job(&gordo_api, &job_api, &pod_api, &gordo, recorder).await;
 
-    Ok(Action::requeue(Duration::from_secs(300)))
+    Ok(Action::requeue(config.reconcile_interval))
 }
 
-fn error_policy(_error: &Error, _ctx: Context<Data>) -> Action {
-    Action::requeue(Duration::from_secs(30))
+/// Record the failure of an object, the returned error holds when to retry it
+fn backoff_error(data: &Data, key: &str, generation: Option<i64>, error: Error) -> (Error, BackoffEntry) {
+    let entry = data
+        .backoffs
+        .failed(key, generation, &data.config.reconcile_backoff, &error);
+    match entry.retry_in {
+        Some(retry_in) => warn!(
+            "Reconcile of {} failed {} times, retrying in {:?}",
+            key, entry.failures, retry_in
+        ),
+        None => warn!("Reconcile of {} failed permanently, waiting for it to change", key),
+    }
+    let error = Error::Backoff {
+        source: Box::new(error),
+        retry_in: entry.retry_in,
+    };
+    (error, entry)
 }
 
-pub async fn init_gordo_controller(client: Client, config: Config) {
-    let gordo: Api<Gordo> = Api::default_namespaced(client.clone());
-    let model: Api<Model> = Api::default_namespaced(client.clone());
-    let workflow: Api<Workflow> = Api::default_namespaced(client.clone());
-
-    log::info!("Starting gordo-controller");
+/// Set or clear the backoff status of a Gordo, errors are only logged
+async fn set_gordo_backoff(client: &Client, gordo: &Gordo, backoff: Option<&BackoffStatus>) {
+    if let (Some(namespace), Some(name)) = (&gordo.metadata.namespace, &gordo.metadata.name) {
+        let gordo_api: Api<Gordo> = Api::namespaced(client.clone(), namespace);
+        if let Err(err) = patch_gordo_backoff(&gordo_api, name, backoff).await {
+            warn!("Failed to patch the backoff status of gordo {:?}: {:?}", name, err);
+        }
+    }
+}
 
-    Controller::new(gordo, ListParams::default())
-        .owns(model, ListParams::default())
-        .owns(workflow, ListParams::default())
-        .shutdown_on_signal()
-        .run(reconcile_gordo, error_policy, Context::new(Data { client, config }))
-        .for_each(|res| async move {
-            match res {
-                Ok(o) => {
-                    info!("Reconciled {:?}", o);
-                    RECONCILE_GORDO_SUCCEDED.with_label_values(&[]).inc();
-                }
-                Err(e) => {
-                    warn!("Reconcile failed: {:?}", e);
-                    RECONCILE_GORDO_ERROR.with_label_values(&[]).inc();
+/// Reconcile a Gordo unless it is backing off from its previous failures
+async fn reconcile_gordo(gordo: Arc<Gordo>, ctx: Context<Data>) -> Result<Action, Error> {
+    let span = reconcile_gordo_span(&gordo);
+    async move {
+        let started_at = Instant::now();
+        let data = ctx.get_ref();
+        let key = ObjectRef::from_obj(gordo.as_ref()).to_string();
+        let generation = gordo.metadata.generation;
+        if let Some(entry) = data.backoffs.pending(&key, generation) {
+            debug!("Backing off from {} after {} failures", key, entry.failures);
+            observe_reconcile("gordo", "backoff", started_at);
+            return Ok(entry.action());
+        }
+        match try_reconcile_gordo(gordo.clone(), ctx.clone()).await {
+            Ok(action) => {
+                let has_backoff_status = gordo.status.as_ref().is_some_and(|status| status.backoff.is_some());
+                let is_deleted = gordo.metadata.deletion_timestamp.is_some();
+                if (data.backoffs.succeeded(&key) || has_backoff_status) && !is_deleted {
+                    set_gordo_backoff(&data.client, &gordo, None).await;
                 }
+                observe_reconcile("gordo", "success", started_at);
+                Ok(action)
             }
-        })
-        .await;
+            Err(error) if error.is_not_found() => {
+                debug!("{} is deleted: {}", key, error);
+                data.backoffs.forget(&key);
+                observe_reconcile("gordo", "error", started_at);
+                Err(error)
+            }
+            Err(error) => {
+                let message = error.to_string();
+                let reason = error.error_name().to_string();
+                let (error, entry) = backoff_error(data, &key, generation, error);
+                let retry_at = entry
+                    .retry_in
+                    .and_then(|retry_in| chrono::Duration::from_std(retry_in).ok())
+                    .map(|retry_in| (Utc::now() + retry_in).to_rfc3339());
+                let status = BackoffStatus {
+                    failures: entry.failures,
+                    reason,
+                    message,
+                    retry_at,
+                };
+                set_gordo_backoff(&data.client, &gordo, Some(&status)).await;
+                observe_reconcile("gordo", "error", started_at);
+                Err(error)
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Reconcile a Model unless it is backing off from its previous failures
+async fn reconcile_model(model: Arc<Model>, ctx: Context<Data>) -> Result<Action, Error> {
+    let span = reconcile_model_span(&model);
+    async move {
+        let started_at = Instant::now();
+        let data = ctx.get_ref();
+        let key = ObjectRef::from_obj(model.as_ref()).to_string();
+        let generation = model.metadata.generation;
+        if let Some(entry) = data.backoffs.pending(&key, generation) {
+            debug!("Backing off from {} after {} failures", key, entry.failures);
+            observe_reconcile("model", "backoff", started_at);
+            return Ok(entry.action());
+        }
+        match try_reconcile_model(model.clone(), ctx.clone()).await {
+            Ok(action) => {
+                data.backoffs.succeeded(&key);
+                observe_reconcile("model", "success", started_at);
+                Ok(action)
+            }
+            Err(error) if error.is_not_found() => {
+                debug!("{} is deleted: {}", key, error);
+                data.backoffs.forget(&key);
+                observe_reconcile("model", "error", started_at);
+                Err(error)
+            }
+            Err(error) => {
+                observe_reconcile("model", "error", started_at);
+                Err(backoff_error(data, &key, generation, error).0)
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+fn error_policy(error: &Error, ctx: Context<Data>) -> Action {
+    match error {
+        Error::Backoff {
+            retry_in: Some(retry_in),
+            ..
+        } => Action::requeue(*retry_in),
+        Error::Backoff { retry_in: None, .. } => Action::await_change(),
+        _ => Action::requeue(ctx.get_ref().config.reconcile_backoff.base),
+    }
+}
+
+async fn try_reconcile_model(model: Arc<Model>, ctx: Context<Data>) -> Result<Action, Error> {
+    let namespace = model
+        .metadata
+        .namespace
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.namespace"))?;
+    let model_name = model
+        .metadata
+        .name
+        .as_ref()
+        .ok_or(Error::MissingKey(".metadata.name"))?;
+    let project_name = model
+        .metadata
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(PROJECT_NAME_LABEL))
+        .ok_or(Error::MissingKey(
+            ".metadata.labels.applications.gordo.equinor.com/project-name",
+        ))?;
+
+    RECONCILE_MODEL_COUNT.with_label_values(&[project_name]).inc();
+
+    let client = ctx.get_ref().client.clone();
+    let recorder = &ctx.get_ref().recorder;
+    let cache = &ctx.get_ref().cache;
+
+    if !cache.is_ready() {
+        debug!("Cache is not ready yet, requeue model {:?}", model_name);
+        return Ok(Action::requeue(Duration::from_secs(5)));
+    }
+    debug!("Reconcile model: {:?}, namespace: {:?}", model_name, namespace);
+
+    // Models of a suspended Gordo are left as they are, they are reconciled again once it is resumed
+    let gordo = cache.gordos.get(namespace, project_name);
+    if gordo.as_ref().is_some_and(is_suspended) {
+        debug!("Gordo {:?} is suspended, skip model {:?}", project_name, model_name);
+        return Ok(Action::await_change());
+    }
+
+    let model_api: Api<Model> = Api::namespaced(client.clone(), namespace);
+    let workflows = cache.workflows.by_project(namespace, project_name);
+    let pods = cache.pods.by_project(namespace, project_name);
+
+    // Each step works on the latest status of the Model, so it doesn't overwrite the previous one's
+    let mut model = (*model).clone();
+    if let Some(new_model) = init_model_status(&model_api, &model).await {
+        model = new_model;
+    }
+    if let Some(new_model) = monitor_wf(&model_api, &model, &workflows, &pods, recorder).await {
+        model = new_model;
+    }
+    if let Some(new_model) = monitor_pods(&model_api, &model, &pods, recorder).await {
+        model = new_model;
+    }
+    if let Some(gordo) = gordo {
+        retry_failed_model(&client, &model_api, &model, &workflows, &gordo, recorder).await;
+    }
+
+    Ok(Action::requeue(ctx.get_ref().config.reconcile_interval))
+}
+
+/// References to the cached models of a `Gordo` which was just resumed, it is still marked as suspended in its status
+fn resumed_gordo_models(cache: &Cache, gordo: &Gordo) -> Vec<ObjectRef<Model>> {
+    match (&gordo.metadata.namespace, &gordo.metadata.name) {
+        (Some(namespace), Some(name)) if !is_suspended(gordo) && has_suspended_condition(gordo) => cache
+            .models
+            .by_project(namespace, name)
+            .iter()
+            .map(ObjectRef::from_obj)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// References to the cached models of the same project which `matches` the `resource`
+fn matching_models<K, F>(cache: &Cache, resource: &K, matches: F) -> Vec<ObjectRef<Model>>
+where
+    K: Resource,
+    F: Fn(&Model, &K) -> bool,
+{
+    let meta = resource.meta();
+    let project_name = meta.labels.as_ref().and_then(|labels| labels.get(PROJECT_NAME_LABEL));
+    match (&meta.namespace, project_name) {
+        (Some(namespace), Some(project_name)) => cache
+            .models
+            .by_project(namespace, project_name)
+            .iter()
+            .filter(|model| matches(model, resource))
+            .map(ObjectRef::from_obj)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+pub async fn init_gordo_controller(client: Client, config: Config, cache: Cache) {
+    log::info!("Starting gordo-controller");
+    if config.watch_namespaces.is_empty() {
+        info!("Watching all namespaces");
+    } else {
+        info!("Watching namespaces: {}", config.watch_namespaces.join(", "));
+    }
+
+    let namespaces = config.watch_namespaces.clone();
+    let context = Context::new(Data {
+        client: client.clone(),
+        config,
+        recorder: Recorder::new(client.clone(), std::env::var("HOSTNAME").ok()),
+        cache,
+        backoffs: Backoffs::default(),
+    });
+    let gordo_controllers = utils::namespaced_apis::<Gordo>(&client, &namespaces)
+        .into_iter()
+        .zip(utils::namespaced_apis::<Model>(&client, &namespaces))
+        .zip(utils::namespaced_apis::<Workflow>(&client, &namespaces))
+        .zip(utils::namespaced_apis::<Job>(&client, &namespaces))
+        .map(|(((gordo, model), workflow), job)| {
+            Controller::new(gordo, ListParams::default())
+                .owns(model, ListParams::default())
+                .owns(workflow, ListParams::default())
+                .owns(job, ListParams::default().labels(GORDO_PROJECT_NAME_LABEL))
+                .shutdown_on_signal()
+                .run(reconcile_gordo, error_policy, context.clone())
+                .for_each(|res| async move {
+                    match res {
+                        Ok(o) => {
+                            info!("Reconciled {:?}", o);
+                            RECONCILE_GORDO_SUCCEDED.with_label_values(&[]).inc();
+                        }
+                        Err(e) => {
+                            warn!("Reconcile failed: {:?}", e);
+                            RECONCILE_GORDO_ERROR.with_label_values(&[]).inc();
+                        }
+                    }
+                })
+                .boxed()
+        });
+    // Models are reconciled on their own, as soon as the workflows or the pods building them change,
+    // or their Gordo is resumed
+    let model_controllers = utils::namespaced_apis::<Model>(&client, &namespaces)
+        .into_iter()
+        .zip(utils::namespaced_apis::<Workflow>(&client, &namespaces))
+        .zip(utils::namespaced_apis::<Pod>(&client, &namespaces))
+        .zip(utils::namespaced_apis::<Gordo>(&client, &namespaces))
+        .map(|(((model, workflow), pod), gordo)| {
+            let project_lp = ListParams::default().labels(PROJECT_NAME_LABEL);
+            let workflow_cache = context.get_ref().cache.clone();
+            let pod_cache = context.get_ref().cache.clone();
+            let gordo_cache = context.get_ref().cache.clone();
+            Controller::new(model, ListParams::default())
+                .watches(workflow, project_lp.clone(), move |workflow| {
+                    matching_models(&workflow_cache, &workflow, is_model_workflow)
+                })
+                .watches(pod, project_lp, move |pod| {
+                    matching_models(&pod_cache, &pod, is_model_pod)
+                })
+                .watches(gordo, ListParams::default(), move |gordo| {
+                    resumed_gordo_models(&gordo_cache, &gordo)
+                })
+                .shutdown_on_signal()
+                .run(reconcile_model, error_policy, context.clone())
+                .for_each(|res| async move {
+                    match res {
+                        Ok(o) => {
+                            debug!("Reconciled {:?}", o);
+                            RECONCILE_MODEL_SUCCEDED.with_label_values(&[]).inc();
+                        }
+                        Err(e) => {
+                            warn!("Reconcile failed: {:?}", e);
+                            RECONCILE_MODEL_ERROR.with_label_values(&[]).inc();
+                        }
+                    }
+                })
+                .boxed()
+        });
+    join_all(gordo_controllers.chain(model_controllers)).await;
 }
@@ -0,0 +1,213 @@
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{Format, JsonFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::redact::{Redactor, REDACTED};
+
+/// Format of the logs written to stderr
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, see `JsonLogFormat`
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format '{}', expected 'text' or 'json'", value)),
+        }
+    }
+}
+
+/// Text logs written to `make_writer`, with the values of the secret fields masked
+pub fn text_fmt_layer<S, W>(
+    make_writer: W,
+    redactor: Redactor,
+) -> tracing_subscriber::fmt::Layer<S, RedactedFields, Format, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .fmt_fields(RedactedFields::new(redactor))
+        .with_writer(make_writer)
+}
+
+/// JSON logs written to `make_writer`, see `JsonLogFormat`
+pub fn json_fmt_layer<S, W>(
+    make_writer: W,
+    redactor: Redactor,
+) -> tracing_subscriber::fmt::Layer<S, JsonFields, JsonLogFormat, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .fmt_fields(JsonFields::new())
+        .event_format(JsonLogFormat::new(redactor))
+        .with_writer(make_writer)
+}
+
+/// Fields of the text logs, the values of the secret ones masked
+pub struct RedactedFields {
+    redactor: Redactor,
+}
+
+impl RedactedFields {
+    pub fn new(redactor: Redactor) -> Self {
+        RedactedFields { redactor }
+    }
+}
+
+impl<'writer> FormatFields<'writer> for RedactedFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = TextVisitor {
+            writer: &mut writer,
+            redactor: &self.redactor,
+            result: Ok(()),
+            is_empty: true,
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+struct TextVisitor<'a, 'writer> {
+    writer: &'a mut Writer<'writer>,
+    redactor: &'a Redactor,
+    result: fmt::Result,
+    is_empty: bool,
+}
+
+impl<'a, 'writer> Visit for TextVisitor<'a, 'writer> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{}", value));
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // The origin of the `log` records is already in their metadata
+        if self.result.is_err() || field.name().starts_with("log.") {
+            return;
+        }
+        let separator = if self.is_empty { "" } else { " " };
+        self.result = match field.name() {
+            "message" => write!(self.writer, "{}{:?}", separator, value),
+            name if self.redactor.is_secret(name) => write!(self.writer, "{}{}={}", separator, name, REDACTED),
+            name => write!(self.writer, "{}{}={:?}", separator, name, value),
+        };
+        self.is_empty = false;
+    }
+}
+
+/// Logs each event as a JSON object on its own line, with its `timestamp`, `level`, `target`, `message` and
+/// fields. The fields of its spans are flattened in it, the innermost span taking precedence, so the logs of the
+/// reconciles have their `gordo`, `model`, `namespace`, `revision` and `trace_id`.
+/// The values of the secret fields are masked
+pub struct JsonLogFormat {
+    redactor: Redactor,
+}
+
+impl JsonLogFormat {
+    pub fn new(redactor: Redactor) -> Self {
+        JsonLogFormat { redactor }
+    }
+
+    fn insert(&self, object: &mut Map<String, Value>, name: &str, value: Value) {
+        if name.starts_with("log.") {
+            return;
+        }
+        let value = if self.redactor.is_secret(name) {
+            Value::String(REDACTED.to_string())
+        } else {
+            value
+        };
+        object.insert(name.to_string(), value);
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonLogFormat
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        // The `log` records hold their own level and target
+        let normalized_metadata = event.normalized_metadata();
+        let metadata = normalized_metadata.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".to_string(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+        );
+        object.insert("level".to_string(), metadata.level().to_string().into());
+        object.insert("target".to_string(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                object.insert("span".to_string(), span.name().into());
+                let extensions = span.extensions();
+                let fields = extensions
+                    .get::<FormattedFields<N>>()
+                    .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok());
+                for (name, value) in fields.unwrap_or_default() {
+                    self.insert(&mut object, &name, value);
+                }
+            }
+        }
+        let mut visitor = JsonVisitor {
+            format: self,
+            object: &mut object,
+        };
+        event.record(&mut visitor);
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+struct JsonVisitor<'a> {
+    format: &'a JsonLogFormat,
+    object: &'a mut Map<String, Value>,
+}
+
+impl<'a> Visit for JsonVisitor<'a> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.format.insert(self.object, field.name(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.format.insert(self.object, field.name(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.format.insert(self.object, field.name(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.format.insert(self.object, field.name(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.format.insert(self.object, field.name(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.format
+            .insert(self.object, field.name(), format!("{:?}", value).into());
+    }
+}
@@ -1,23 +1,85 @@
 use actix_web::{middleware, web, App, HttpServer};
 use actix_web_prom::PrometheusMetricsBuilder;
 use errors::Error;
-use gordo_controller::{crd, errors, init_gordo_controller, views, Config};
-use kube::client::Client;
+use gordo_controller::crd::metrics::kube_api::KubeApiMetricsLayer;
+use gordo_controller::leader::{LeaderElection, LeaderState};
+use gordo_controller::telemetry::{init_telemetry, shutdown_telemetry, HttpRootSpanBuilder, KubeApiTraceLayer};
+use gordo_controller::{cache::Cache, crd, errors, init_gordo_controller, views, Config};
+use kube::client::ClientBuilder;
 use log::{info, warn};
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use prometheus::Registry;
+use std::convert::TryFrom;
 use std::env::vars;
+use std::sync::Arc;
+use tracing_actix_web::TracingLogger;
 
 #[actix_rt::main]
 async fn main() -> Result<(), errors::Error> {
-    //TODO do not forget about RUST_LOG env in all deployment scripts
-    env_logger::init();
-
     let gordo_config = Config::from_envs(vars()).unwrap();
+    //TODO do not forget about RUST_LOG env in all deployment scripts
+    init_telemetry(&gordo_config).expect("Could not set up telemetry");
     info!("Starting with config: {:?}", gordo_config);
     let bind_address = format!("{}:{}", &gordo_config.server_host, gordo_config.server_port);
+    let webhook_host = gordo_config.server_host.clone();
+    let webhook_port = gordo_config.webhook_port;
+    let webhook_tls_cert = gordo_config.webhook_tls_cert.clone();
+    let webhook_tls_key = gordo_config.webhook_tls_key.clone();
+
+    let kube_config = kube::Config::infer()
+        .await
+        .map_err(|err| Error::KubeError(kube::Error::InferConfig(err)))?;
+    let default_namespace = kube_config.default_namespace.clone();
+    let client = ClientBuilder::try_from(kube_config)
+        .map_err(Error::KubeError)?
+        .with_layer(&KubeApiMetricsLayer)
+        .with_layer(&KubeApiTraceLayer)
+        .build();
 
-    let client = Client::try_default().await.map_err(Error::KubeError)?;
-    let controller = init_gordo_controller(client.clone(), gordo_config);
+    let (cache, reflectors) = Cache::new(&client, &gordo_config.watch_namespaces);
+
+    // The HTTP API is served by all replicas, the controller only runs in the leader
+    let leader_election = if gordo_config.leader_election {
+        let identity = std::env::var("HOSTNAME").unwrap_or_else(|_| format!("gordo-controller-{}", std::process::id()));
+        let lease_namespace = gordo_config
+            .leader_election_namespace
+            .clone()
+            .unwrap_or_else(|| default_namespace.clone());
+        Some(Arc::new(LeaderElection::new(
+            client.clone(),
+            &lease_namespace,
+            &gordo_config.leader_election_lease_name,
+            &identity,
+        )))
+    } else {
+        None
+    };
+    let leader_state = match &leader_election {
+        Some(leader_election) => leader_election.state(),
+        None => {
+            let leader_state = LeaderState::default();
+            leader_state.set_leader(true);
+            leader_state
+        }
+    };
+    let controller_client = client.clone();
+    let controller_config = gordo_config.clone();
+    let controller_cache = cache.clone();
+    let run_controller = move || {
+        init_gordo_controller(
+            controller_client.clone(),
+            controller_config.clone(),
+            controller_cache.clone(),
+        )
+    };
+    let controller_election = leader_election.clone();
+    let controller = async move {
+        match controller_election {
+            // A replica losing the lease keeps serving the HTTP API and competes for the lease again
+            Some(leader_election) => leader_election.run_while_leader(run_controller).await,
+            None => run_controller().await,
+        }
+    };
 
     let registry = Registry::new();
     crd::metrics::custom_metrics(&registry);
@@ -27,29 +89,79 @@ async fn main() -> Result<(), errors::Error> {
         .build()
         .unwrap();
 
+    let app_state = web::Data::new(views::AppState {
+        client,
+        config: gordo_config,
+        cache,
+        default_namespace,
+    });
+    let leader_state = web::Data::new(leader_state);
+    let webhook_state = app_state.clone();
     let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(views::AppState { client: client.clone() }))
+            .app_data(app_state.clone())
+            .app_data(leader_state.clone())
             .wrap(prometheus.clone())
             .wrap(middleware::Logger::default().exclude("/health").exclude("/metrics"))
             .wrap(middleware::Compress::default())
-            .service(web::resource("/health").to(views::health))
-            .service(web::resource("/gordos").to(views::gordos))
-            .service(web::resource("/gordos/{name}").to(views::get_gordo))
-            .service(web::resource("/models").to(views::models))
-            .service(web::resource("/models/{gordo_name}").to(views::models_by_gordo))
+            .wrap(TracingLogger::<HttpRootSpanBuilder>::new())
+            .configure(views::api_routes)
     })
     .bind(&bind_address)
-    .expect(&format!("Could not bind to '{}'", &bind_address));
+    .unwrap_or_else(|err| panic!("Could not bind to '{}': {}", &bind_address, err))
+    .run();
+
+    // The API server only calls admission webhooks over HTTPS, on their own port so it can't reach the API routes
+    let webhook_server = match (&webhook_tls_cert, &webhook_tls_key) {
+        (Some(tls_cert), Some(tls_key)) => {
+            let webhook_address = format!("{}:{}", &webhook_host, webhook_port);
+            info!("Serving the admission webhooks over HTTPS on '{}'", webhook_address);
+            let mut ssl_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+            ssl_builder
+                .set_private_key_file(tls_key, SslFiletype::PEM)
+                .unwrap_or_else(|err| panic!("Could not load TLS key '{}': {}", tls_key, err));
+            ssl_builder
+                .set_certificate_chain_file(tls_cert)
+                .unwrap_or_else(|err| panic!("Could not load TLS certificate '{}': {}", tls_cert, err));
+            let webhook_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(webhook_state.clone())
+                    .wrap(middleware::Logger::default())
+                    .wrap(TracingLogger::<HttpRootSpanBuilder>::new())
+                    .configure(views::webhook_routes)
+            })
+            .bind_openssl(&webhook_address, ssl_builder)
+            .unwrap_or_else(|err| panic!("Could not bind to '{}': {}", &webhook_address, err))
+            .run();
+            Some(webhook_server)
+        }
+        _ => None,
+    };
+    let webhook_server = async move {
+        match webhook_server {
+            Some(webhook_server) => webhook_server.await,
+            None => futures::future::pending().await,
+        }
+    };
 
     tokio::select! {
-        _ = server.run() => {
+        _ = server => {
             info!("actix exited");
         }
+        _ = webhook_server => {
+            info!("webhook server exited");
+        }
         _ = controller => {
             warn!("gordo controller drained");
         }
+        _ = reflectors => {
+            warn!("cache reflectors drained");
+        }
+    }
+    if let Some(leader_election) = leader_election {
+        leader_election.release().await;
     }
+    shutdown_telemetry();
 
     Ok(())
 }
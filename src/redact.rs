@@ -0,0 +1,96 @@
+use serde_json::Value;
+
+/// Replacement of the masked values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Patterns of the keys whose values are masked in the logs, unless `REDACT_PATTERNS` is set
+pub const DEFAULT_REDACT_PATTERNS: &str = "*_KEY,*SECRET*,*TOKEN*";
+
+/// Masks the values of the keys matching one of its patterns, where `*` matches any characters.
+/// Keys are matched case-insensitively, so `*TOKEN*` also masks `apiToken`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Redactor {
+    patterns: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String]) -> Self {
+        Redactor {
+            patterns: patterns.iter().map(|pattern| pattern.to_uppercase()).collect(),
+        }
+    }
+
+    /// `true` if the value of `key` must not be logged
+    pub fn is_secret(&self, key: &str) -> bool {
+        let key = key.to_uppercase();
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern.as_bytes(), key.as_bytes()))
+    }
+
+    /// `value`, or `REDACTED` if `key` is a secret
+    pub fn redact<'a>(&self, key: &str, value: &'a str) -> &'a str {
+        if self.is_secret(key) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+
+    /// Mask the members of the objects with a secret key, and the `value` of the objects naming a secret key:
+    /// environment variables like `{"name": "API_TOKEN", "value": "..."}` and JSON patch operations like
+    /// `{"op": "add", "path": "/spec/deploy-environment/API_TOKEN", "value": "..."}`
+    pub fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                let env_var_name = object.get("name").and_then(Value::as_str);
+                let patched_key = object
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .and_then(|path| path.rsplit('/').next());
+                let names_secret = env_var_name
+                    .into_iter()
+                    .chain(patched_key)
+                    .any(|key| self.is_secret(key));
+                for (key, member) in object.iter_mut() {
+                    if self.is_secret(key) || (names_secret && key == "value") {
+                        *member = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_json(member);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact_json(item)),
+            _ => (),
+        }
+    }
+}
+
+/// `true` if the `key` matches the `pattern`, `*` matching any characters
+fn matches_pattern(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut pattern_index, mut key_index) = (0, 0);
+    // Position of the last `*` in the pattern, and of the key when it was reached
+    let mut star: Option<(usize, usize)> = None;
+    while key_index < key.len() {
+        match pattern.get(pattern_index) {
+            Some(b'*') => {
+                star = Some((pattern_index, key_index));
+                pattern_index += 1;
+            }
+            Some(character) if *character == key[key_index] => {
+                pattern_index += 1;
+                key_index += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((star_index, star_key_index)) => {
+                    pattern_index = star_index + 1;
+                    key_index = star_key_index + 1;
+                    star = Some((star_index, star_key_index + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..].iter().all(|character| *character == b'*')
+}
@@ -0,0 +1,199 @@
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use futures::future::BoxFuture;
+use http::{Request, Response};
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::trace::{TraceContextExt, TraceError};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::field::{display, Empty};
+use tracing::{info_span, Instrument, Span};
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer as _;
+
+use crate::cache::{PROJECT_NAME_LABEL, PROJECT_REVISION_LABEL};
+use crate::crd::gordo::Gordo;
+use crate::crd::metrics::kube_api::api_resource;
+use crate::crd::model::Model;
+use crate::logging::{json_fmt_layer, text_fmt_layer, LogFormat};
+use crate::Config;
+
+/// `service.name` of the exported spans
+pub const SERVICE_NAME: &str = "gordo-controller";
+
+/// Tracer exporting the spans to the OTLP/HTTP collector at `endpoint`, e.g. `http://otel-collector:4318`
+pub fn otlp_tracer(endpoint: &str) -> Result<trace::Tracer, TraceError> {
+    let traces_endpoint = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().http().with_endpoint(traces_endpoint))
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", SERVICE_NAME)])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+}
+
+/// Log in the configured format, filtered by `RUST_LOG`, and export the spans if `OTLP_ENDPOINT` is set.
+/// `log` records of the controller and its dependencies are logged as `tracing` events
+pub fn init_telemetry(config: &Config) -> Result<(), TraceError> {
+    let text_layer = match config.log_format {
+        LogFormat::Text => {
+            Some(text_fmt_layer(std::io::stderr, config.redactor.clone()).with_filter(EnvFilter::from_default_env()))
+        }
+        LogFormat::Json => None,
+    };
+    let json_layer = match config.log_format {
+        LogFormat::Json => {
+            Some(json_fmt_layer(std::io::stderr, config.redactor.clone()).with_filter(EnvFilter::from_default_env()))
+        }
+        LogFormat::Text => None,
+    };
+    // Spans are exported whatever `RUST_LOG` is, the kube client's own debug spans are left out
+    let otel_layer = match &config.otlp_endpoint {
+        Some(endpoint) => Some(
+            tracing_opentelemetry::layer()
+                .with_tracer(otlp_tracer(endpoint)?)
+                .with_filter(LevelFilter::INFO),
+        ),
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(text_layer)
+        .with(json_layer)
+        .with(otel_layer)
+        .init();
+    Ok(())
+}
+
+/// Export the spans which are still buffered
+pub fn shutdown_telemetry() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Record the trace ID of the `span` in its `trace_id` field, so its logs can be found from the trace.
+/// Left empty when the spans are not exported
+pub fn record_trace_id(span: &Span) {
+    let context = span.context();
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        span.record("trace_id", display(span_context.trace_id()));
+    }
+}
+
+/// Root span of the reconcile of a Gordo
+pub fn reconcile_gordo_span(gordo: &Gordo) -> Span {
+    let revision = gordo.status.as_ref().map(|status| status.project_revision.as_str());
+    let span = info_span!(
+        "reconcile_gordo",
+        gordo = gordo.metadata.name.as_deref().unwrap_or_default(),
+        namespace = gordo.metadata.namespace.as_deref().unwrap_or_default(),
+        revision = revision.unwrap_or_default(),
+        trace_id = Empty,
+    );
+    record_trace_id(&span);
+    span
+}
+
+/// Root span of the reconcile of a Model
+pub fn reconcile_model_span(model: &Model) -> Span {
+    let label = |name: &str| {
+        model
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(name))
+            .map(String::as_str)
+            .unwrap_or_default()
+    };
+    let span = info_span!(
+        "reconcile_model",
+        model = model.metadata.name.as_deref().unwrap_or_default(),
+        namespace = model.metadata.namespace.as_deref().unwrap_or_default(),
+        gordo = label(PROJECT_NAME_LABEL),
+        revision = label(PROJECT_REVISION_LABEL),
+        trace_id = Empty,
+    );
+    record_trace_id(&span);
+    span
+}
+
+/// Root spans of the HTTP requests, the health checks and metrics scrapes are not traced
+pub struct HttpRootSpanBuilder;
+
+impl RootSpanBuilder for HttpRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        match request.path() {
+            "/health" | "/metrics" => Span::none(),
+            _ => DefaultRootSpanBuilder::on_request_start(request),
+        }
+    }
+
+    fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, actix_web::Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// Layer of the kube client tracing each of its calls in a span
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KubeApiTraceLayer;
+
+impl<S> Layer<S> for KubeApiTraceLayer {
+    type Service = KubeApiTrace<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        KubeApiTrace { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KubeApiTrace<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for KubeApiTrace<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let verb = match request.extensions().get::<&'static str>() {
+            Some(verb) => verb.to_string(),
+            None => request.method().as_str().to_lowercase(),
+        };
+        let resource = api_resource(request.uri().path());
+        let span = info_span!(
+            "kube_api",
+            otel.name = %format!("{} {}", verb, resource),
+            otel.kind = "client",
+            verb = %verb,
+            resource = %resource,
+            http.status_code = Empty,
+        );
+        // The kube client sends the request from its own task, which follows the span entered here
+        let future = span.in_scope(|| self.inner.call(request));
+        let response_span = span.clone();
+        Box::pin(
+            async move {
+                let response = future.await;
+                if let Ok(response) = &response {
+                    response_span.record("http.status_code", response.status().as_u16());
+                }
+                response
+            }
+            .instrument(span),
+        )
+    }
+}
@@ -1,9 +1,14 @@
+use crate::crd::gordo::gordo::ConfigMapGeneratorSpec;
 use crate::errors::Error;
 use k8s_openapi::{
     api::core::v1::EnvVar,
     apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference},
 };
-use kube::api::Resource;
+use kube::api::{Api, Resource};
+use kube::client::Client;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 
 pub fn object_to_owner_reference<K: Resource<DynamicType = ()>>(meta: ObjectMeta) -> Result<OwnerReference, Error> {
     Ok(OwnerReference {
@@ -15,6 +20,17 @@ pub fn object_to_owner_reference<K: Resource<DynamicType = ()>>(meta: ObjectMeta
     })
 }
 
+/// One `Api` per namespace, or a single cluster-wide `Api` if `namespaces` is empty
+pub fn namespaced_apis<K: Resource<DynamicType = ()>>(client: &Client, namespaces: &[String]) -> Vec<Api<K>> {
+    if namespaces.is_empty() {
+        return vec![Api::all(client.clone())];
+    }
+    namespaces
+        .iter()
+        .map(|namespace| Api::namespaced(client.clone(), namespace))
+        .collect()
+}
+
 pub fn resource_names<T: Resource<DynamicType = ()>>(resource: &Vec<T>) -> String {
     // TODO intersperse
     let words: Vec<String> = resource
@@ -47,6 +63,41 @@ pub fn env_var(name: &str, value: &str) -> EnvVar {
     }
 }
 
-pub fn get_revision() -> String {
-    chrono::Utc::now().timestamp_millis().to_string()
+/// Recursively sort the keys of JSON objects, so the serialized form doesn't depend on `HashMap` ordering
+fn sort_json_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<String, Value> = map
+                .into_iter()
+                .map(|(key, value)| (key, sort_json_keys(value)))
+                .collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_json_keys).collect()),
+        value => value,
+    }
+}
+
+/// Fields of the Gordo spec which are handled by the controller, changing them doesn't redeploy the project
+pub const REVISION_IGNORED_FIELDS: &[&str] = &[
+    "max-model-retries",
+    "model-retry-error-types",
+    "suspend",
+    "revision-retention",
+];
+
+/// Deterministic project revision of a Gordo spec.
+/// The same spec and `counter` always give the same revision, `counter` allows redeploying an unchanged spec.
+pub fn get_revision(spec: &ConfigMapGeneratorSpec, counter: u32) -> String {
+    let mut spec_value = serde_json::to_value(spec).map(sort_json_keys).unwrap_or(Value::Null);
+    if let Value::Object(fields) = &mut spec_value {
+        for field in REVISION_IGNORED_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(spec_value.to_string().as_bytes());
+    hasher.update(counter.to_be_bytes());
+    let digest = format!("{:x}", hasher.finalize());
+    digest[..16].to_string()
 }
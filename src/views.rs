@@ -1,13 +1,87 @@
+use crate::cache::Cache;
+use crate::crd::gordo::gordo::request_gordo_rebuild;
+use crate::crd::gordo::rollback::{check_rollback, request_gordo_rollback};
+use crate::crd::gordo::suspend::patch_gordo_suspend;
 use crate::crd::model::{filter_models_on_gordo, Model};
+use crate::crd::{self, crds_yaml};
 use crate::errors::Error;
-use crate::Gordo;
+use crate::leader::LeaderState;
+use crate::webhook;
+use crate::{Config, Gordo};
 use actix_web::{error, http, http::StatusCode, web, HttpRequest, HttpResponse, HttpResponseBuilder};
-use kube::api::ListParams;
+use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview};
+use kube::core::DynamicObject;
 use kube::{Api, Client};
+use log::{debug, info, warn};
 use serde::Serialize;
+use std::convert::TryInto;
 
 pub struct AppState {
     pub client: Client,
+    pub config: Config,
+    pub cache: Cache,
+    /// Namespace of the controller, used by the routes which are not namespace-qualified
+    pub default_namespace: String,
+}
+
+impl AppState {
+    /// The cache, only once it has been filled up
+    fn cache(&self) -> Result<&Cache, Error> {
+        if !self.cache.is_ready() {
+            return Err(Error::NotReady("cache"));
+        }
+        Ok(&self.cache)
+    }
+
+    /// Check that a namespace-qualified route is watched by the controller
+    fn watched_namespace<'a>(&self, namespace: &'a str) -> Result<&'a str, Error> {
+        if !self.config.is_watched_namespace(namespace) {
+            return Err(Error::NotFound(format!("namespace '{}'", namespace)));
+        }
+        Ok(namespace)
+    }
+}
+
+/// Routes of the HTTP API
+pub fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/health").to(health))
+        .service(web::resource("/crds").to(crds))
+        .service(web::resource("/gordos").to(gordos))
+        .service(web::resource("/gordos/{name}").to(get_gordo))
+        .service(web::resource("/gordos/{name}/rebuild").route(web::post().to(rebuild_gordo)))
+        .service(web::resource("/gordos/{name}/rollback/{revision}").route(web::post().to(rollback_gordo)))
+        .service(web::resource("/gordos/{name}/suspend").route(web::post().to(suspend_gordo)))
+        .service(web::resource("/gordos/{name}/resume").route(web::post().to(resume_gordo)))
+        .service(web::resource("/models").to(models))
+        .service(web::resource("/models/{gordo_name}").to(models_by_gordo))
+        .service(web::resource("/all-namespaces/gordos").to(all_gordos))
+        .service(web::resource("/all-namespaces/models").to(all_models))
+        .service(web::resource("/namespaces/{namespace}/gordos").to(namespaced_gordos))
+        .service(web::resource("/namespaces/{namespace}/gordos/{name}").to(get_namespaced_gordo))
+        .service(
+            web::resource("/namespaces/{namespace}/gordos/{name}/rebuild")
+                .route(web::post().to(rebuild_namespaced_gordo)),
+        )
+        .service(
+            web::resource("/namespaces/{namespace}/gordos/{name}/rollback/{revision}")
+                .route(web::post().to(rollback_namespaced_gordo)),
+        )
+        .service(
+            web::resource("/namespaces/{namespace}/gordos/{name}/suspend")
+                .route(web::post().to(suspend_namespaced_gordo)),
+        )
+        .service(
+            web::resource("/namespaces/{namespace}/gordos/{name}/resume")
+                .route(web::post().to(resume_namespaced_gordo)),
+        )
+        .service(web::resource("/namespaces/{namespace}/models").to(namespaced_models))
+        .service(web::resource("/namespaces/{namespace}/models/{gordo_name}").to(namespaced_models_by_gordo));
+}
+
+/// Routes of the admission webhooks, served apart from the API over HTTPS
+pub fn webhook_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/validate/gordo").route(web::post().to(validate_gordo)))
+        .service(web::resource("/mutate/gordo").route(web::post().to(mutate_gordo)));
 }
 
 #[derive(Serialize)]
@@ -23,83 +97,348 @@ impl error::ResponseError for Error {
     }
 
     fn status_code(&self) -> http::StatusCode {
-        http::StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            Error::NotFound(_) => http::StatusCode::NOT_FOUND,
+            Error::NotReady(_) => http::StatusCode::SERVICE_UNAVAILABLE,
+            Error::Conflict(_) => http::StatusCode::CONFLICT,
+            _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+fn find_models_by_gordo(cache: &Cache, namespace: &str, gordo_name: &str) -> Vec<Model> {
+    // All models who's owner references have this gordo's name and matches the project revision number
+    match cache.gordos.get(namespace, gordo_name) {
+        Some(gordo) => {
+            let project_models = cache.models.by_project(namespace, gordo_name);
+            filter_models_on_gordo(&gordo, &project_models)
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+        None => Vec::with_capacity(0), // No models found for a Gordo which doesn't exist
+    }
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    role: &'static str,
+}
+
+// Simple health check endpoint, also reporting if this replica is the leader
+pub async fn health(req: HttpRequest) -> HttpResponse {
+    match req.app_data::<web::Data<LeaderState>>() {
+        Some(leader_state) => HttpResponse::Ok().json(HealthResponse {
+            role: leader_state.role(),
+        }),
+        None => HttpResponse::new(StatusCode::OK),
+    }
+}
+
+// CRDs of the controller as YAML, generated from the same types the controller deserializes
+pub async fn crds(_req: HttpRequest) -> actix_web::Result<HttpResponse, Error> {
+    let yaml = crds_yaml(&crd::crds()).map_err(Error::YamlError)?;
+    Ok(HttpResponse::Ok().content_type("application/yaml").body(yaml))
+}
+
+// Validating admission webhook for Gordos
+pub async fn validate_gordo(review: web::Json<AdmissionReview<DynamicObject>>) -> HttpResponse {
+    let request: AdmissionRequest<DynamicObject> = match review.into_inner().try_into() {
+        Ok(request) => request,
+        Err(err) => {
+            warn!("Invalid admission review: {}", err);
+            return HttpResponse::BadRequest().json(AdmissionResponse::invalid(err.to_string()).into_review());
+        }
+    };
+    let mut response = AdmissionResponse::from(&request);
+    if let Some(gordo) = &request.object {
+        let errors = webhook::validate_gordo(gordo);
+        if !errors.is_empty() {
+            info!("Rejecting Gordo {:?}: {}", gordo.metadata.name, errors.join("; "));
+            response = response.deny(errors.join("; "));
+        }
     }
+    HttpResponse::Ok().json(response.into_review())
 }
 
-// Simple health check endpoint
-pub async fn health(_req: HttpRequest) -> HttpResponse {
-    HttpResponse::new(StatusCode::OK)
+// Mutating admission webhook for Gordos, fills in the defaults of the deploy Job
+pub async fn mutate_gordo(
+    data: web::Data<AppState>,
+    review: web::Json<AdmissionReview<DynamicObject>>,
+) -> HttpResponse {
+    let request: AdmissionRequest<DynamicObject> = match review.into_inner().try_into() {
+        Ok(request) => request,
+        Err(err) => {
+            warn!("Invalid admission review: {}", err);
+            return HttpResponse::BadRequest().json(AdmissionResponse::invalid(err.to_string()).into_review());
+        }
+    };
+    let mut response = AdmissionResponse::from(&request);
+    if let Some(gordo) = &request.object {
+        let patch = webhook::mutate_gordo(gordo, &data.config);
+        if !patch.0.is_empty() {
+            // The patch holds the default deploy environment
+            let mut logged_patch = serde_json::to_value(&patch).unwrap_or_default();
+            data.config.redactor.redact_json(&mut logged_patch);
+            debug!("Applying defaults to Gordo {:?}: {}", gordo.metadata.name, logged_patch);
+            response = match response.with_patch(patch) {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!(
+                        "Unable to serialize the patch of Gordo {:?}: {}",
+                        gordo.metadata.name, err
+                    );
+                    AdmissionResponse::from(&request).deny(err.to_string())
+                }
+            };
+        }
+    }
+    HttpResponse::Ok().json(response.into_review())
 }
 
-// List current gordos
+// List current gordos in the controller's own namespace
 pub async fn gordos(data: web::Data<AppState>, _req: HttpRequest) -> actix_web::Result<web::Json<Vec<Gordo>>, Error> {
-    let gordo_api: Api<Gordo> = Api::default_namespaced(data.client.clone());
-    let lp = ListParams::default();
+    let gordos = data.cache()?.gordos.namespaced(&data.default_namespace);
+    Ok(web::Json(gordos))
+}
+
+// List current gordos in all watched namespaces
+pub async fn all_gordos(
+    data: web::Data<AppState>,
+    _req: HttpRequest,
+) -> actix_web::Result<web::Json<Vec<Gordo>>, Error> {
+    let gordos = data.cache()?.gordos.state();
+    Ok(web::Json(gordos))
+}
 
-    let gordo_list = gordo_api.list(&lp).await.map_err(Error::KubeError)?;
-    let gordos: Vec<Gordo> = gordo_list.into_iter().collect();
+// List current gordos in a namespace
+pub async fn namespaced_gordos(
+    data: web::Data<AppState>,
+    namespace: web::Path<String>,
+) -> actix_web::Result<web::Json<Vec<Gordo>>, Error> {
+    let namespace = data.watched_namespace(&namespace)?;
+    let gordos = data.cache()?.gordos.namespaced(namespace);
     Ok(web::Json(gordos))
 }
 
-// Get a gordo by name
+// Get a gordo by name in the controller's own namespace
 pub async fn get_gordo(
     data: web::Data<AppState>,
     name: web::Path<String>,
 ) -> actix_web::Result<web::Json<Gordo>, Error> {
-    let gordo_api: Api<Gordo> = Api::default_namespaced(data.client.clone());
-    let lp = ListParams::default();
-
-    let name_str = name.as_str();
-    let gordo_list = gordo_api.list(&lp).await.map_err(Error::KubeError)?;
-    let gordo: Option<Gordo> = gordo_list
-        .into_iter()
-        .filter(|gordo| gordo.metadata.name == Some(name_str.to_string()))
-        .nth(0);
-    match gordo {
+    match data.cache()?.gordos.get(&data.default_namespace, &name) {
         Some(item) => Ok(web::Json(item)),
-        None => Err(Error::NotFound("gordo")),
+        None => Err(gordo_not_found(&data.default_namespace, &name)),
+    }
+}
+
+// Get a gordo by namespace and name
+pub async fn get_namespaced_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (namespace, name) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
+
+    match data.cache()?.gordos.get(namespace, &name) {
+        Some(item) => Ok(web::Json(item)),
+        None => Err(gordo_not_found(namespace, &name)),
+    }
+}
+
+/// API of the gordos in the namespace, if the gordo `name` is known
+fn gordo_api(data: &AppState, namespace: &str, name: &str) -> Result<Api<Gordo>, Error> {
+    data.cache()?
+        .gordos
+        .get(namespace, name)
+        .ok_or_else(|| gordo_not_found(namespace, name))?;
+    Ok(Api::namespaced(data.client.clone(), namespace))
+}
+
+fn gordo_not_found(namespace: &str, name: &str) -> Error {
+    Error::NotFound(format!("gordo '{}/{}'", namespace, name))
+}
+
+/// Error of a request on a gordo through the API server
+fn gordo_error(err: kube::Error, namespace: &str, name: &str) -> Error {
+    match err {
+        kube::Error::Api(response) if response.code == 404 => gordo_not_found(namespace, name),
+        err => Error::KubeError(err),
+    }
+}
+
+/// Request a rebuild of a gordo, the controller deploys it again even if its spec didn't change
+async fn rebuild_gordo_in(data: &AppState, namespace: &str, name: &str) -> Result<Gordo, Error> {
+    let gordo_api = gordo_api(data, namespace, name)?;
+    let gordo = request_gordo_rebuild(&gordo_api, name)
+        .await
+        .map_err(|err| gordo_error(err, namespace, name))?;
+    info!("Requested rebuild of gordo '{}' in namespace '{}'", name, namespace);
+    Ok(gordo)
+}
+
+/// Request a gordo to point back to a previous revision, as long as its models are still present
+async fn rollback_gordo_in(data: &AppState, namespace: &str, name: &str, revision: &str) -> Result<Gordo, Error> {
+    let gordo_api = gordo_api(data, namespace, name)?;
+    let cache = data.cache()?;
+    if let Some(gordo) = cache.gordos.get(namespace, name) {
+        let models = cache.models.by_project(namespace, name);
+        if let Err(reason) = check_rollback(&gordo, &models, revision) {
+            info!(
+                "Not rolling back gordo '{}' in namespace '{}': {}",
+                name, namespace, reason
+            );
+            return Err(Error::Conflict(format!("Can't roll back: {}", reason)));
+        }
     }
+    let gordo = request_gordo_rollback(&gordo_api, name, revision)
+        .await
+        .map_err(|err| gordo_error(err, namespace, name))?;
+    info!(
+        "Requested rollback of gordo '{}' in namespace '{}' to revision {}",
+        name, namespace, revision
+    );
+    Ok(gordo)
+}
+
+/// Suspend or resume a gordo
+async fn suspend_gordo_in(data: &AppState, namespace: &str, name: &str, suspend: bool) -> Result<Gordo, Error> {
+    let gordo_api = gordo_api(data, namespace, name)?;
+    let gordo = patch_gordo_suspend(&gordo_api, name, suspend)
+        .await
+        .map_err(|err| gordo_error(err, namespace, name))?;
+    info!(
+        "{} gordo '{}' in namespace '{}'",
+        if suspend { "Suspended" } else { "Resumed" },
+        name,
+        namespace
+    );
+    Ok(gordo)
+}
+
+// Rebuild a gordo by name in the controller's own namespace
+pub async fn rebuild_gordo(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let gordo = rebuild_gordo_in(&data, &data.default_namespace, &name).await?;
+    Ok(web::Json(gordo))
+}
+
+// Rebuild a gordo by namespace and name
+pub async fn rebuild_namespaced_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (namespace, name) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
+    let gordo = rebuild_gordo_in(&data, namespace, &name).await?;
+    Ok(web::Json(gordo))
+}
+
+// Roll back a gordo by name in the controller's own namespace
+pub async fn rollback_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (name, revision) = path.into_inner();
+    let gordo = rollback_gordo_in(&data, &data.default_namespace, &name, &revision).await?;
+    Ok(web::Json(gordo))
+}
+
+// Roll back a gordo by namespace and name
+pub async fn rollback_namespaced_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (namespace, name, revision) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
+    let gordo = rollback_gordo_in(&data, namespace, &name, &revision).await?;
+    Ok(web::Json(gordo))
+}
+
+// Suspend a gordo by name in the controller's own namespace
+pub async fn suspend_gordo(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let gordo = suspend_gordo_in(&data, &data.default_namespace, &name, true).await?;
+    Ok(web::Json(gordo))
+}
+
+// Resume a gordo by name in the controller's own namespace
+pub async fn resume_gordo(
+    data: web::Data<AppState>,
+    name: web::Path<String>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let gordo = suspend_gordo_in(&data, &data.default_namespace, &name, false).await?;
+    Ok(web::Json(gordo))
+}
+
+// Suspend a gordo by namespace and name
+pub async fn suspend_namespaced_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (namespace, name) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
+    let gordo = suspend_gordo_in(&data, namespace, &name, true).await?;
+    Ok(web::Json(gordo))
+}
+
+// Resume a gordo by namespace and name
+pub async fn resume_namespaced_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Gordo>, Error> {
+    let (namespace, name) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
+    let gordo = suspend_gordo_in(&data, namespace, &name, false).await?;
+    Ok(web::Json(gordo))
 }
 
-// List current models
+// List current models in the controller's own namespace
 pub async fn models(data: web::Data<AppState>, _req: HttpRequest) -> actix_web::Result<web::Json<Vec<Model>>, Error> {
-    let model_api: Api<Model> = Api::default_namespaced(data.client.clone());
-    let lp = ListParams::default();
+    let models = data.cache()?.models.namespaced(&data.default_namespace);
+    Ok(web::Json(models))
+}
 
-    let model_list = model_api.list(&lp).await.map_err(Error::KubeError)?;
-    let models: Vec<Model> = model_list.into_iter().collect();
+// List current models in all watched namespaces
+pub async fn all_models(
+    data: web::Data<AppState>,
+    _req: HttpRequest,
+) -> actix_web::Result<web::Json<Vec<Model>>, Error> {
+    let models = data.cache()?.models.state();
+    Ok(web::Json(models))
+}
+
+// List current models in a namespace
+pub async fn namespaced_models(
+    data: web::Data<AppState>,
+    namespace: web::Path<String>,
+) -> actix_web::Result<web::Json<Vec<Model>>, Error> {
+    let namespace = data.watched_namespace(&namespace)?;
+    let models = data.cache()?.models.namespaced(namespace);
     Ok(web::Json(models))
 }
 
 // List current models belonging to a specific Gordo at the same project revision number
+// in the controller's own namespace
 pub async fn models_by_gordo(
     data: web::Data<AppState>,
     gordo_name: web::Path<String>,
 ) -> actix_web::Result<web::Json<Vec<Model>>, Error> {
-    let gordo_api: Api<Gordo> = Api::default_namespaced(data.client.clone());
-    let model_api: Api<Model> = Api::default_namespaced(data.client.clone());
-    let lp = ListParams::default();
-
-    let name_str = gordo_name.as_str();
-    let gordo_list = gordo_api.list(&lp).await.map_err(Error::KubeError)?;
-    // Get the gordo by name, can result in None
-    let gordo_by_name: Option<Gordo> = gordo_list
-        .into_iter()
-        .filter(|gordo| gordo.metadata.name == Some(name_str.to_string()))
-        .nth(0);
+    let models = find_models_by_gordo(data.cache()?, &data.default_namespace, &gordo_name);
+    Ok(web::Json(models))
+}
 
-    // All models who's owner references have this gordo's name and matches the project revision number
-    let models = match gordo_by_name {
-        Some(gordo) => {
-            let model_list = model_api.list(&lp).await.map_err(Error::KubeError)?;
-            let all_models: Vec<Model> = model_list.into_iter().collect();
-            filter_models_on_gordo(&gordo, &all_models)
-                .map(ToOwned::to_owned)
-                .collect()
-        }
-        None => Vec::with_capacity(0), // No models found for a Gordo which doesn't exist
-    };
+// List current models belonging to a specific Gordo in a namespace
+pub async fn namespaced_models_by_gordo(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> actix_web::Result<web::Json<Vec<Model>>, Error> {
+    let (namespace, gordo_name) = path.into_inner();
+    let namespace = data.watched_namespace(&namespace)?;
 
+    let models = find_models_by_gordo(data.cache()?, namespace, &gordo_name);
     Ok(web::Json(models))
 }
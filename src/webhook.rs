@@ -0,0 +1,212 @@
+use kube::core::DynamicObject;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+use crate::crd::gordo::gordo::{ConfigMapGeneratorSpec, DeployEnvValue};
+use crate::deploy_job::{DEPLOY_JOB_NAME_PREFIX, MAX_NAME_LENGTH};
+use crate::Config;
+
+/// Fields of a machine config which hold YAML documents
+const MACHINE_YAML_FIELDS: &[&str] = &["dataset", "model"];
+
+/// `true` if `tag` is a valid docker image tag
+fn is_valid_image_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() || first == '_' => (),
+        _ => return false,
+    }
+    tag.len() <= 128 && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+fn validate_deploy_environment(spec: &Value, errors: &mut Vec<String>) {
+    if let Some(Value::Object(environment)) = spec.get("deploy-environment") {
+        for (name, value) in environment {
+            match serde_json::from_value::<DeployEnvValue>(value.clone()) {
+                Ok(DeployEnvValue::Value(_)) => (),
+                Ok(DeployEnvValue::ValueFrom { value_from }) => {
+                    let n_sources = [
+                        value_from.secret_key_ref.is_some(),
+                        value_from.config_map_key_ref.is_some(),
+                        value_from.field_ref.is_some(),
+                        value_from.resource_field_ref.is_some(),
+                    ]
+                    .iter()
+                    .filter(|is_set| **is_set)
+                    .count();
+                    if n_sources != 1 {
+                        errors.push(format!(
+                            "spec.deploy-environment.{}.valueFrom must have exactly one source, got {}",
+                            name, n_sources
+                        ));
+                    }
+                }
+                Err(_) => errors.push(format!(
+                    "spec.deploy-environment.{} must be a string or a valueFrom reference, got {}",
+                    name, value
+                )),
+            }
+        }
+    }
+}
+
+fn validate_gordo_name(name: &str, generation: i64, errors: &mut Vec<String>) {
+    // The next generation has to fit in the deploy Job name without being truncated
+    let job_name_length = DEPLOY_JOB_NAME_PREFIX.len() + name.len() + 1 + (generation + 1).to_string().len();
+    if job_name_length > MAX_NAME_LENGTH {
+        errors.push(format!(
+            "metadata.name '{}' is too long, its deploy job name would be {} characters long, the limit is {}",
+            name, job_name_length, MAX_NAME_LENGTH
+        ));
+    }
+}
+
+fn validate_machine(index: usize, machine: &Value, names: &mut HashSet<String>, errors: &mut Vec<String>) {
+    let path = format!("spec.config.machines[{}]", index);
+    let machine = match machine.as_object() {
+        Some(machine) => machine,
+        None => {
+            errors.push(format!("{} must be an object", path));
+            return;
+        }
+    };
+    match machine.get("name").and_then(Value::as_str) {
+        Some(name) if !name.is_empty() => {
+            if name.len() > MAX_NAME_LENGTH {
+                errors.push(format!(
+                    "{}.name '{}' is {} characters long, the limit is {}",
+                    path,
+                    name,
+                    name.len(),
+                    MAX_NAME_LENGTH
+                ));
+            }
+            if !names.insert(name.to_string()) {
+                errors.push(format!("{}.name '{}' is duplicated", path, name));
+            }
+        }
+        _ => errors.push(format!("{}.name is required", path)),
+    }
+    for field in MACHINE_YAML_FIELDS {
+        match machine.get(*field) {
+            Some(Value::String(document)) => {
+                if let Err(err) = serde_yaml::from_str::<serde_yaml::Value>(document) {
+                    errors.push(format!("{}.{} is not valid YAML: {}", path, field, err));
+                }
+            }
+            Some(Value::Null) | None => (),
+            Some(_) => errors.push(format!("{}.{} must be a YAML string", path, field)),
+        }
+    }
+}
+
+/// Check a `Gordo` submitted to the API server, returns the reasons to reject it
+pub fn validate_gordo(gordo: &DynamicObject) -> Vec<String> {
+    let mut errors: Vec<String> = vec![];
+    let spec = match gordo.data.get("spec") {
+        Some(spec) => spec,
+        None => return vec!["spec is required".to_string()],
+    };
+    validate_deploy_environment(spec, &mut errors);
+    if !errors.is_empty() {
+        return errors;
+    }
+    let spec: ConfigMapGeneratorSpec = match serde_json::from_value(spec.clone()) {
+        Ok(spec) => spec,
+        Err(err) => return vec![format!("spec is invalid: {}", err)],
+    };
+
+    if !is_valid_image_tag(&spec.deploy_version) {
+        errors.push(format!(
+            "spec.deploy-version '{}' is not a valid image tag",
+            spec.deploy_version
+        ));
+    }
+    if let Some(name) = &gordo.metadata.name {
+        validate_gordo_name(name, gordo.metadata.generation.unwrap_or(0), &mut errors);
+    }
+    let mut names: HashSet<String> = HashSet::new();
+    for (index, machine) in spec.config.models().iter().enumerate() {
+        validate_machine(index, machine, &mut names, &mut errors);
+    }
+    errors
+}
+
+/// `true` if the `field` of the spec is unset
+fn is_unset(spec: &Value, field: &str) -> bool {
+    matches!(spec.get(field), None | Some(Value::Null))
+}
+
+/// Repository of the deploy image when the Gordo doesn't set one, as resolved by `deploy_image`.
+/// `DEPLOY_IMAGE` is only split into a repository if it is hosted on `docker_registry`
+fn default_deploy_repository(config: &Config, docker_registry: &str) -> Option<String> {
+    if !config.deploy_repository.is_empty() {
+        return Some(config.deploy_repository.clone());
+    }
+    config
+        .deploy_image
+        .strip_prefix(&format!("{}/", docker_registry))
+        .map(ToOwned::to_owned)
+}
+
+/// Spec of a `Gordo` with the controller defaults of the deploy Job filled in
+fn gordo_spec_with_defaults(spec: &Value, config: &Config) -> Value {
+    let mut spec = spec.clone();
+    if is_unset(&spec, "docker-registry") {
+        spec["docker-registry"] = config.docker_registry.clone().into();
+    }
+    if is_unset(&spec, "deploy-repository") {
+        let docker_registry = spec["docker-registry"].as_str().unwrap_or_default().to_string();
+        if let Some(deploy_repository) = default_deploy_repository(config, &docker_registry) {
+            spec["deploy-repository"] = deploy_repository.into();
+        }
+    }
+    if let Ok(Value::Object(pod_template)) = serde_json::to_value(&config.deploy_pod_template) {
+        if !pod_template.is_empty() && is_unset(&spec, "deploy-pod-template") {
+            spec["deploy-pod-template"] = json!({});
+        }
+        if let Some(spec_pod_template) = spec.get_mut("deploy-pod-template").and_then(Value::as_object_mut) {
+            for (key, value) in pod_template {
+                spec_pod_template.entry(key).or_insert(value);
+            }
+        }
+    }
+    // Literal values may be credentials, only the references are stored in the Gordo. The literal ones are still
+    // added to the deploy Job by `create_deploy_job`
+    let default_references: Vec<(&String, &DeployEnvValue)> = config
+        .default_deploy_environment
+        .iter()
+        .flatten()
+        .filter(|(_, value)| matches!(value, DeployEnvValue::ValueFrom { .. }))
+        .collect();
+    if !default_references.is_empty() {
+        if is_unset(&spec, "deploy-environment") {
+            spec["deploy-environment"] = json!({});
+        }
+        if let Some(environment) = spec.get_mut("deploy-environment").and_then(Value::as_object_mut) {
+            for (key, value) in default_references {
+                // Values of the Gordo take precedence over the defaults, as in `create_deploy_job`
+                if let Ok(value) = serde_json::to_value(value) {
+                    environment.entry(key.clone()).or_insert(value);
+                }
+            }
+        }
+    }
+    if let Some(default_deploy_env_from) = &config.default_deploy_env_from {
+        if is_unset(&spec, "deploy-env-from") && !default_deploy_env_from.is_empty() {
+            spec["deploy-env-from"] = json!(default_deploy_env_from);
+        }
+    }
+    spec
+}
+
+/// JSON patch applying the controller defaults to a `Gordo` submitted to the API server
+pub fn mutate_gordo(gordo: &DynamicObject, config: &Config) -> json_patch::Patch {
+    match gordo.data.get("spec") {
+        Some(spec) => json_patch::diff(
+            &json!({ "spec": spec }),
+            &json!({ "spec": gordo_spec_with_defaults(spec, config) }),
+        ),
+        None => json_patch::Patch(vec![]),
+    }
+}
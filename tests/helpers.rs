@@ -2,8 +2,12 @@
 use kube::api::{DeleteParams, ListParams};
 use kube::{api::Api, client::Client};
 use serde_yaml;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use gordo_controller::crd::gordo::Gordo;
+use gordo_controller::Config;
 use serde::de::DeserializeOwned;
 
 // Get the `APIClient` using current kube config
@@ -33,3 +37,107 @@ where
 pub fn load_gordo_resource(client: Client, namespace: &str) -> Api<Gordo> {
     Api::namespaced(client.clone(), namespace)
 }
+
+/// Minimal controller `Config` with the given extra environment variables
+pub fn config(extra_envs: Vec<(&str, &str)>) -> Config {
+    let mut envs: Vec<(String, String)> = vec![
+        (
+            "DEPLOY_IMAGE".to_string(),
+            "ghcr.io/equinor/gordo-base:latest".to_string(),
+        ),
+        ("DOCKER_REGISTRY".to_string(), "ghcr.io".to_string()),
+        ("DEFAULT_DEPLOY_ENVIRONMENT".to_string(), "{}".to_string()),
+        ("RESOURCES_LABELS".to_string(), "{}".to_string()),
+    ];
+    // The extra environment variables take precedence over the defaults above
+    envs.retain(|(key, _)| !extra_envs.iter().any(|(extra_key, _)| extra_key == key));
+    envs.extend(
+        extra_envs
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+    );
+    Config::from_envs(envs.into_iter()).expect("Unable to load config")
+}
+
+/// Read an HTTP request up to the end of its body
+pub async fn read_http_request(socket: &mut tokio::net::TcpStream) -> Vec<u8> {
+    let mut request = vec![];
+    let mut buffer = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..n]);
+        let text = String::from_utf8_lossy(&request).to_lowercase();
+        if let Some(headers_end) = text.find("\r\n\r\n") {
+            let content_length: usize = text[..headers_end]
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .map(|value| value.trim().parse().unwrap())
+                .unwrap_or(0);
+            if request.len() >= headers_end + 4 + content_length {
+                return request;
+            }
+        }
+        if n == 0 {
+            return request;
+        }
+    }
+}
+
+/// Request received by the `fake_api_server`
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    pub method: String,
+    /// Path and query of the request
+    pub path: String,
+    pub body: String,
+}
+
+/// Stand-in for the k8s API server, answering each request with the status code and JSON body from `respond`.
+/// Returns a client of it and the requests it received
+pub async fn fake_api_server<F>(respond: F) -> (Client, Arc<Mutex<Vec<ApiRequest>>>)
+where
+    F: Fn(&ApiRequest) -> (u16, String) + Send + Sync + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let respond = Arc::new(respond);
+    let received = requests.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let respond = respond.clone();
+            let received = received.clone();
+            tokio::spawn(async move {
+                // The client keeps the connection alive between its requests
+                loop {
+                    let raw = read_http_request(&mut socket).await;
+                    if raw.is_empty() {
+                        return;
+                    }
+                    let raw = String::from_utf8_lossy(&raw).to_string();
+                    let (head, body) = raw.split_once("\r\n\r\n").unwrap_or((&raw, ""));
+                    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+                    let request = ApiRequest {
+                        method: request_line.next().unwrap_or_default().to_string(),
+                        path: request_line.next().unwrap_or_default().to_string(),
+                        body: body.to_string(),
+                    };
+                    let (status, body) = respond(&request);
+                    received.lock().unwrap().push(request);
+                    let response = format!(
+                        "HTTP/1.1 {} Fake\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    if socket.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    let client = Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap();
+    (client, requests)
+}
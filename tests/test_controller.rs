@@ -1,13 +1,60 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use kube::api::{DeleteParams, ListParams, PostParams};
+use chrono::Utc;
+use k8s_openapi::api::batch::v1::{JobCondition, JobStatus};
+use k8s_openapi::api::coordination::v1::LeaseSpec;
+use k8s_openapi::api::core::v1::{ContainerState, ContainerStateTerminated, ContainerStatus, Pod, PodStatus};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use kube::api::{Api, DeleteParams, ListParams, PostParams};
+use kube::core::DynamicObject;
+use kube::runtime::controller::Action;
+use kube::runtime::watcher;
+use opentelemetry::trace::TracerProvider;
+use tokio::io::AsyncWriteExt;
+use tracing_subscriber::layer::SubscriberExt;
 
 mod helpers;
 
-use gordo_controller::crd::gordo::gordo::GordoStatus;
+use gordo_controller::backoff::{BackoffConfig, Backoffs};
+use gordo_controller::cache::{IndexedStore, PROJECT_NAME_LABEL};
+use gordo_controller::crd::argo::{
+    find_model_build_node, find_model_workflows, is_failed_workflow, is_model_build_node, is_model_workflow,
+    model_build_phase, workflow_retry, workflows_condition, ArgoNodeStatus, ArgoWorkflowPhase, ArgoWorkflowSpec,
+    ArgoWorkflowStatus, Workflow, MODEL_BUILDER_TEMPLATE, WF_NUMBER_LABEL, WF_RETRY_LABEL,
+};
+use gordo_controller::crd::condition::{find_condition, set_condition, Condition};
+use gordo_controller::crd::gordo::cleanup::{cleanup_gordo, has_cleanup_finalizer, GORDO_CLEANUP_FINALIZER};
+use gordo_controller::crd::gordo::gordo::{
+    requested_rebuild, DeployEnvValue, DeployPhase, GordoStatus, RevisionHistoryEntry, RevisionRetention,
+    CONDITION_SUSPENDED, REBUILD_REQUESTED_AT_ANNOTATION,
+};
+use gordo_controller::crd::gordo::retention::{expired_revisions, revision_retention};
+use gordo_controller::crd::gordo::rollback::{check_rollback, rolled_back_status};
+use gordo_controller::crd::gordo::suspend::{has_suspended_condition, is_suspended, is_unfinished_workflow};
 use gordo_controller::crd::gordo::Gordo;
-use gordo_controller::crd::model::{filter_models_on_gordo, Model};
+use gordo_controller::crd::job::deploy_job_status;
+use gordo_controller::crd::metrics::kube_api::api_resource;
+use gordo_controller::crd::metrics::{
+    observe_model_build, remove_gordo_metrics, seconds_between, update_gordo_metrics, GORDO_MODELS_BUILT,
+    GORDO_MODELS_EXPECTED, MODELS, MODEL_BUILD_DURATION,
+};
+use gordo_controller::crd::model::retry::{retry_workflow, should_retry_model};
+use gordo_controller::crd::model::{
+    filter_models_on_gordo, first_model_created_at, models_built_condition, Model, ModelPhase, ModelStatus,
+};
+use gordo_controller::crd::pod::is_model_pod;
+use gordo_controller::crd::{crds, crds_yaml};
 use gordo_controller::deploy_job::{create_deploy_job, deploy_job_name};
+use gordo_controller::errors::Error;
+use gordo_controller::events::{new_event, EventType, Recorder};
+use gordo_controller::leader::{acquire_lease_spec, is_lease_expired, LeaderElection};
+use gordo_controller::logging::{json_fmt_layer, text_fmt_layer, LogFormat};
+use gordo_controller::redact::{Redactor, DEFAULT_REDACT_PATTERNS, REDACTED};
+use gordo_controller::telemetry::{otlp_tracer, reconcile_gordo_span, shutdown_telemetry, SERVICE_NAME};
+use gordo_controller::utils::get_revision;
+use gordo_controller::webhook::{mutate_gordo, validate_gordo};
 use gordo_controller::{Config, GordoEnvironmentConfig};
 
 // We can create a gordo using the `example-gordo.yaml` file in the repo.
@@ -81,7 +128,7 @@ fn test_deploy_job_injects_project_version() {
     ];
     let config = Config::from_envs(envs.into_iter()).unwrap();
 
-    let deploy_job = create_deploy_job(&gordo, &config).expect("Unable to create deploy job");
+    let deploy_job = create_deploy_job(&gordo, &config, "1234").expect("Unable to create deploy job");
 
     let template = deploy_job.spec.unwrap().template;
 
@@ -90,7 +137,68 @@ fn test_deploy_job_injects_project_version() {
         .as_ref()
         .unwrap()
         .iter()
-        .any(|ev| ev.name == "WORKFLOW_GENERATOR_PROJECT_REVISION"));
+        .any(|ev| ev.name == "WORKFLOW_GENERATOR_PROJECT_REVISION" && ev.value.as_deref() == Some("1234")));
+}
+
+#[test]
+fn test_get_revision() {
+    let gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let revision = get_revision(&gordo.spec, 0);
+    assert_eq!(revision.len(), 16);
+
+    // Same spec, same revision, regardless of the deploy-environment ordering
+    let mut same_gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let mut environment: Vec<(String, DeployEnvValue)> =
+        same_gordo.spec.deploy_environment.take().unwrap().into_iter().collect();
+    environment.reverse();
+    let environment: HashMap<String, DeployEnvValue> = environment.into_iter().collect();
+    same_gordo.spec.deploy_environment = Some(environment);
+    assert_eq!(get_revision(&same_gordo.spec, 0), revision);
+
+    // Redeploy of the same spec
+    assert_ne!(get_revision(&gordo.spec, 1), revision);
+
+    // Changed spec
+    let mut changed_gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    changed_gordo.spec.deploy_version = "0.50.0".to_string();
+    assert_ne!(get_revision(&changed_gordo.spec, 0), revision);
+}
+
+// Changes of the fields handled by the controller keep the project revision, so they don't redeploy it
+fn assert_same_revision(change: impl Fn(&mut Gordo)) {
+    let gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let mut changed_gordo = gordo.clone();
+    change(&mut changed_gordo);
+    assert_ne!(
+        serde_json::to_value(&changed_gordo.spec).unwrap(),
+        serde_json::to_value(&gordo.spec).unwrap()
+    );
+    assert_eq!(get_revision(&changed_gordo.spec, 0), get_revision(&gordo.spec, 0));
+}
+
+#[test]
+fn test_get_revision_ignores_max_model_retries() {
+    assert_same_revision(|gordo| gordo.spec.max_model_retries = Some(3));
+}
+
+#[test]
+fn test_get_revision_ignores_model_retry_error_types() {
+    assert_same_revision(|gordo| gordo.spec.model_retry_error_types = Some(vec!["DataError".to_string()]));
+}
+
+#[test]
+fn test_get_revision_ignores_suspend() {
+    assert_same_revision(|gordo| gordo.spec.suspend = Some(true));
+}
+
+#[test]
+fn test_get_revision_ignores_revision_retention() {
+    assert_same_revision(|gordo| {
+        gordo.spec.revision_retention = Some(RevisionRetention {
+            keep_revisions: Some(2),
+            max_age_seconds: None,
+        })
+    });
 }
 
 #[test]
@@ -121,3 +229,1544 @@ fn test_filter_models_on_gordo() {
 
     assert_eq!(filter_models_on_gordo(&gordo, &models).count(), 1);
 }
+
+#[test]
+fn test_deploy_job_pod_template() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.uid = Some("6571b980-8824-4b4f-b87c-639c40ef91e3".to_string());
+    let pod_spec = |gordo: &Gordo, config: &Config| {
+        let job = create_deploy_job(gordo, config, "1234").expect("Unable to create deploy job");
+        job.spec.unwrap().template.spec.unwrap()
+    };
+
+    // Built-in defaults
+    let spec = pod_spec(&gordo, &helpers::config(vec![]));
+    let container = &spec.containers[0];
+    assert_eq!(container.image_pull_policy.as_deref(), Some("Always"));
+    let limits = container.resources.as_ref().unwrap().limits.as_ref().unwrap();
+    assert_eq!(limits["memory"].0, "1000Mi");
+    assert!(spec.node_selector.is_none());
+
+    // Controller defaults, partially overridden by the Gordo
+    let config = helpers::config(vec![(
+        "DEPLOY_POD_TEMPLATE",
+        r#"{"nodeSelector": {"agentpool": "system"}, "imagePullPolicy": "IfNotPresent",
+            "tolerations": [{"key": "CriticalAddonsOnly", "operator": "Exists"}]}"#,
+    )]);
+    gordo.spec.deploy_pod_template = Some(
+        serde_json::from_value(serde_json::json!({
+            "resources": {"limits": {"memory": "4Gi"}},
+            "imagePullPolicy": "Never",
+            "priorityClassName": "gordo-deploy",
+            "imagePullSecrets": [{"name": "registry-credentials"}],
+        }))
+        .unwrap(),
+    );
+    let spec = pod_spec(&gordo, &config);
+    let container = &spec.containers[0];
+    assert_eq!(container.image_pull_policy.as_deref(), Some("Never"));
+    let resources = container.resources.as_ref().unwrap();
+    assert_eq!(resources.limits.as_ref().unwrap()["memory"].0, "4Gi");
+    assert!(resources.requests.is_none());
+    assert_eq!(spec.node_selector.unwrap()["agentpool"], "system");
+    assert_eq!(spec.tolerations.unwrap()[0].key.as_deref(), Some("CriticalAddonsOnly"));
+    assert_eq!(spec.priority_class_name.as_deref(), Some("gordo-deploy"));
+    assert_eq!(
+        spec.image_pull_secrets.unwrap()[0].name.as_deref(),
+        Some("registry-credentials")
+    );
+}
+
+#[test]
+fn test_deploy_job_environment_references() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.uid = Some("6571b980-8824-4b4f-b87c-639c40ef91e3".to_string());
+    gordo.spec = serde_json::from_value(serde_json::json!({
+        "deploy-version": "latest",
+        "deploy-environment": {
+            "DL_SERVICE_AUTH_STR": {
+                "valueFrom": {"secretKeyRef": {"name": "dlserviceauth", "key": "tenant_id_secret"}},
+            },
+            "ARGO_LOG_LEVEL": "debug",
+        },
+        "deploy-env-from": [{"secretRef": {"name": "project-secrets"}}],
+        "config": {"machines": []},
+    }))
+    .unwrap();
+    let config = helpers::config(vec![
+        (
+            "DEFAULT_DEPLOY_ENVIRONMENT",
+            r#"{"REGISTRY_TOKEN": {"valueFrom": {"configMapKeyRef": {"name": "registry", "key": "token"}}}}"#,
+        ),
+        (
+            "DEFAULT_DEPLOY_ENV_FROM",
+            r#"[{"configMapRef": {"name": "gordo-defaults"}}, {"secretRef": {"name": "project-secrets"}}]"#,
+        ),
+    ]);
+
+    let job = create_deploy_job(&gordo, &config, "1234").expect("Unable to create deploy job");
+    let container = job.spec.unwrap().template.spec.unwrap().containers[0].clone();
+    let env = container.env.unwrap();
+    let env_var = |name: &str| env.iter().find(|env_var| env_var.name == name).unwrap().clone();
+
+    let secret_key_ref = env_var("DL_SERVICE_AUTH_STR")
+        .value_from
+        .unwrap()
+        .secret_key_ref
+        .unwrap();
+    assert_eq!(secret_key_ref.name.as_deref(), Some("dlserviceauth"));
+    assert_eq!(secret_key_ref.key, "tenant_id_secret");
+    assert!(env_var("DL_SERVICE_AUTH_STR").value.is_none());
+    assert_eq!(env_var("ARGO_LOG_LEVEL").value.as_deref(), Some("debug"));
+    let config_map_key_ref = env_var("REGISTRY_TOKEN")
+        .value_from
+        .unwrap()
+        .config_map_key_ref
+        .unwrap();
+    assert_eq!(config_map_key_ref.key, "token");
+
+    // The defaults first, without repeating the sources of the Gordo
+    let env_from = container.env_from.unwrap();
+    assert_eq!(env_from.len(), 2);
+    assert_eq!(
+        env_from[0].config_map_ref.as_ref().unwrap().name.as_deref(),
+        Some("gordo-defaults")
+    );
+    assert_eq!(
+        env_from[1].secret_ref.as_ref().unwrap().name.as_deref(),
+        Some("project-secrets")
+    );
+}
+
+#[test]
+fn test_config_watch_namespaces() {
+    // Cluster-wide by default
+    let config = helpers::config(vec![]);
+    assert!(config.watch_namespaces.is_empty());
+    assert!(config.is_watched_namespace("any-namespace"));
+
+    let config = helpers::config(vec![("WATCH_NAMESPACES", "project-a, project-b,,")]);
+    assert_eq!(config.watch_namespaces, vec!["project-a", "project-b"]);
+    assert!(config.is_watched_namespace("project-b"));
+    assert!(!config.is_watched_namespace("default"));
+}
+
+#[test]
+fn test_deploy_job_status() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.uid = Some("6571b980-8824-4b4f-b87c-639c40ef91e3".to_string());
+    let config = helpers::config(vec![]);
+    let mut job = create_deploy_job(&gordo, &config, "1234").expect("Unable to create deploy job");
+    let job_name = job.metadata.name.clone().unwrap();
+
+    // Just created
+    let deploy_job = deploy_job_status(&job, &[]);
+    assert_eq!(deploy_job.phase, DeployPhase::Pending);
+    assert_eq!(deploy_job.job_name, job_name);
+
+    job.status = Some(JobStatus {
+        active: Some(1),
+        ..JobStatus::default()
+    });
+    assert_eq!(deploy_job_status(&job, &[]).phase, DeployPhase::Running);
+
+    // Failed, the message is taken from the terminated deploy container
+    job.status = Some(JobStatus {
+        failed: Some(1),
+        conditions: Some(vec![JobCondition {
+            type_: "Failed".to_string(),
+            status: "True".to_string(),
+            message: Some("Job has reached the specified backoff limit".to_string()),
+            ..JobCondition::default()
+        }]),
+        ..JobStatus::default()
+    });
+    assert_eq!(
+        deploy_job_status(&job, &[]).message.as_deref(),
+        Some("Job has reached the specified backoff limit")
+    );
+    let pod = Pod {
+        status: Some(PodStatus {
+            container_statuses: Some(vec![ContainerStatus {
+                name: "gordo-deploy".to_string(),
+                state: Some(ContainerState {
+                    terminated: Some(ContainerStateTerminated {
+                        exit_code: 1,
+                        message: Some("Invalid gordo config\n".to_string()),
+                        ..ContainerStateTerminated::default()
+                    }),
+                    ..ContainerState::default()
+                }),
+                ..ContainerStatus::default()
+            }]),
+            ..PodStatus::default()
+        }),
+        ..Pod::default()
+    };
+    let deploy_job = deploy_job_status(&job, &[pod]);
+    assert_eq!(deploy_job.phase, DeployPhase::Failed);
+    assert_eq!(deploy_job.message.as_deref(), Some("Invalid gordo config"));
+
+    job.status = Some(JobStatus {
+        succeeded: Some(1),
+        ..JobStatus::default()
+    });
+    assert_eq!(deploy_job_status(&job, &[]).phase, DeployPhase::Succeeded);
+}
+
+#[test]
+fn test_set_condition() {
+    let mut conditions: Vec<Condition> = vec![];
+    assert!(set_condition(
+        &mut conditions,
+        Condition::from_bool("Ready", false, "InProgress", "", Some(1))
+    ));
+    conditions[0].last_transition_time = "2020-01-01T00:00:00Z".to_string();
+
+    // Same status keeps the transition time
+    assert!(!set_condition(
+        &mut conditions,
+        Condition::from_bool("Ready", false, "InProgress", "", Some(1))
+    ));
+    assert_eq!(conditions[0].last_transition_time, "2020-01-01T00:00:00Z");
+
+    // New status is a transition
+    assert!(set_condition(
+        &mut conditions,
+        Condition::from_bool("Ready", true, "Succeeded", "", Some(1))
+    ));
+    assert_eq!(conditions.len(), 1);
+    assert!(conditions[0].is_true());
+    assert_ne!(conditions[0].last_transition_time, "2020-01-01T00:00:00Z");
+}
+
+#[test]
+fn test_model_status_conditions() {
+    let mut status = ModelStatus {
+        phase: ModelPhase::Failed,
+        error_type: Some("DataLakeTimeoutError".to_string()),
+        message: Some("Timed out".to_string()),
+        ..ModelStatus::default()
+    };
+    status.update_conditions(Some(2));
+    assert_eq!(status.conditions.len(), 3);
+
+    let failed = find_condition(&status.conditions, "Failed").unwrap();
+    assert!(failed.is_true());
+    // Arbitrary error types are kept out of the reason
+    assert_eq!(failed.reason, "BuildFailed");
+    assert_eq!(failed.message, "DataLakeTimeoutError: Timed out");
+    assert_eq!(failed.observed_generation, Some(2));
+    assert!(!find_condition(&status.conditions, "Ready").unwrap().is_true());
+
+    status.phase = ModelPhase::Succeeded;
+    status.update_conditions(Some(2));
+    assert!(find_condition(&status.conditions, "Ready").unwrap().is_true());
+    assert!(!find_condition(&status.conditions, "Failed").unwrap().is_true());
+}
+
+#[test]
+fn test_models_built_condition() {
+    // Not built, nor failing, before the revision has any model
+    let condition = models_built_condition(0, 0, Some(1));
+    assert_eq!(condition.type_, "AllModelsBuilt");
+    assert_eq!(condition.status, "Unknown");
+    assert_eq!(condition.reason, "NoModels");
+
+    let condition = models_built_condition(3, 1, Some(1));
+    assert!(!condition.is_true());
+    assert_eq!(condition.reason, "ModelsPending");
+    assert_eq!(condition.message, "1 of 3 models built");
+
+    let condition = models_built_condition(3, 3, Some(1));
+    assert!(condition.is_true());
+    assert_eq!(condition.reason, "ModelsBuilt");
+}
+
+#[test]
+fn test_workflows_condition() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.status = Some(GordoStatus {
+        project_revision: "1234".to_string(),
+        ..GordoStatus::default()
+    });
+
+    let workflow = |revision: &str, phase: ArgoWorkflowPhase| {
+        let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+        workflow.metadata.labels = Some(BTreeMap::from([
+            (
+                "applications.gordo.equinor.com/project-name".to_string(),
+                "test-project-name".to_string(),
+            ),
+            (
+                "applications.gordo.equinor.com/project-revision".to_string(),
+                revision.to_string(),
+            ),
+        ]));
+        workflow.status = Some(ArgoWorkflowStatus {
+            phase: Some(phase),
+            ..ArgoWorkflowStatus::default()
+        });
+        workflow
+    };
+
+    assert_eq!(workflows_condition(&gordo, &[]).status, "Unknown");
+
+    // Workflows of the previous revision are ignored
+    let mut workflows = vec![
+        workflow("1234", ArgoWorkflowPhase::Succeeded),
+        workflow("1000", ArgoWorkflowPhase::Failed),
+    ];
+    assert!(workflows_condition(&gordo, &workflows).is_true());
+
+    workflows.push(workflow("1234", ArgoWorkflowPhase::Running));
+    assert_eq!(workflows_condition(&gordo, &workflows).reason, "WorkflowsRunning");
+
+    workflows.push(workflow("1234", ArgoWorkflowPhase::Error));
+    assert_eq!(workflows_condition(&gordo, &workflows).reason, "WorkflowsFailed");
+
+    // The failed workflows are replaced by their retries
+    let mut retry = workflow("1234", ArgoWorkflowPhase::Succeeded);
+    retry
+        .metadata
+        .labels
+        .as_mut()
+        .unwrap()
+        .insert(WF_RETRY_LABEL.to_string(), "1".to_string());
+    let workflows = vec![workflow("1234", ArgoWorkflowPhase::Error), retry];
+    assert!(workflows_condition(&gordo, &workflows).is_true());
+}
+
+#[test]
+fn test_new_event() {
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.namespace = Some("project-a".to_string());
+    let status = ModelStatus {
+        phase: ModelPhase::Failed,
+        error_type: Some("DataLakeTimeoutError".to_string()),
+        message: Some("Timed out".to_string()),
+        ..ModelStatus::default()
+    };
+
+    let event = new_event(
+        &model,
+        EventType::Warning,
+        status.reason(),
+        &status.phase_message(),
+        Some("gordo-controller-0"),
+    );
+    assert_eq!(event.metadata.namespace.as_deref(), Some("project-a"));
+    assert_eq!(event.metadata.generate_name.as_deref(), Some("gordo-model-name."));
+    assert_eq!(event.involved_object.kind.as_deref(), Some("Model"));
+    assert_eq!(event.involved_object.name.as_deref(), Some("gordo-model-name"));
+    assert_eq!(event.type_.as_deref(), Some("Warning"));
+    assert_eq!(event.reason.as_deref(), Some("BuildFailed"));
+    assert_eq!(event.message.as_deref(), Some("DataLakeTimeoutError: Timed out"));
+    assert_eq!(event.reporting_instance.as_deref(), Some("gordo-controller-0"));
+}
+
+#[tokio::test]
+async fn test_indexed_store() {
+    let model = |namespace: &str, name: &str, project_name: &str| {
+        let mut model: Model = helpers::deserialize_config("example-model.yaml");
+        model.metadata.namespace = Some(namespace.to_string());
+        model.metadata.name = Some(name.to_string());
+        model.metadata.labels = Some(BTreeMap::from([(
+            PROJECT_NAME_LABEL.to_string(),
+            project_name.to_string(),
+        )]));
+        model
+    };
+    let events: Vec<watcher::Result<watcher::Event<Model>>> = vec![
+        Ok(watcher::Event::Restarted(vec![
+            model("project-a", "model-1", "project-1"),
+            model("project-a", "model-2", "project-1"),
+            model("project-b", "model-1", "project-1"),
+        ])),
+        Ok(watcher::Event::Applied(model("project-a", "model-3", "project-2"))),
+        Ok(watcher::Event::Applied(model("project-a", "model-2", "project-2"))),
+        Ok(watcher::Event::Deleted(model("project-b", "model-1", "project-1"))),
+    ];
+    let (store, reflector) = IndexedStore::from_watchers(vec![futures::stream::iter(events)]);
+    assert!(!store.is_ready());
+    reflector.await;
+    assert!(store.is_ready());
+
+    let names = |models: Vec<Model>| {
+        let mut names: Vec<String> = models.into_iter().filter_map(|model| model.metadata.name).collect();
+        names.sort();
+        names
+    };
+    assert_eq!(store.state().len(), 3);
+    assert_eq!(names(store.by_project("project-a", "project-1")), vec!["model-1"]);
+    assert_eq!(
+        names(store.by_project("project-a", "project-2")),
+        vec!["model-2", "model-3"]
+    );
+    assert!(store.by_project("project-b", "project-1").is_empty());
+    assert_eq!(
+        names(store.namespaced("project-a")),
+        vec!["model-1", "model-2", "model-3"]
+    );
+    assert!(store.get("project-a", "model-3").is_some());
+    assert!(store.get("project-b", "model-1").is_none());
+}
+
+#[test]
+fn test_model_workflows_and_pods() {
+    let labels = |revision: &str, model_name: &str, wf_number: &str| {
+        Some(BTreeMap::from([
+            (PROJECT_NAME_LABEL.to_string(), "test-project-name".to_string()),
+            (
+                "applications.gordo.equinor.com/project-revision".to_string(),
+                revision.to_string(),
+            ),
+            (
+                "applications.gordo.equinor.com/model-name".to_string(),
+                model_name.to_string(),
+            ),
+            (WF_NUMBER_LABEL.to_string(), wf_number.to_string()),
+        ]))
+    };
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.labels = labels("1234", "model-1", "0");
+
+    let workflow = |revision: &str, wf_number: &str| {
+        let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+        workflow.metadata.labels = labels(revision, "", wf_number);
+        workflow
+    };
+    assert!(is_model_workflow(&model, &workflow("1234", "0")));
+    assert!(!is_model_workflow(&model, &workflow("1234", "1")));
+    assert!(!is_model_workflow(&model, &workflow("1000", "0")));
+
+    let pod = |revision: &str, model_name: &str| {
+        let mut pod = Pod::default();
+        pod.metadata.labels = labels(revision, model_name, "0");
+        pod
+    };
+    assert!(is_model_pod(&model, &pod("1234", "model-1")));
+    assert!(!is_model_pod(&model, &pod("1234", "model-2")));
+    assert!(!is_model_pod(&model, &pod("1000", "model-1")));
+    assert!(!is_model_pod(&model, &Pod::default()));
+}
+
+#[test]
+fn test_acquire_lease_spec() {
+    let now = Utc::now();
+    let spec = acquire_lease_spec(&LeaseSpec::default(), "replica-1", now).expect("Free lease is acquired");
+    assert_eq!(spec.holder_identity.as_deref(), Some("replica-1"));
+    assert_eq!(spec.lease_transitions, Some(1));
+
+    // Held by another replica until it expires
+    assert!(acquire_lease_spec(&spec, "replica-2", now + chrono::Duration::seconds(5)).is_none());
+    let taken_over = acquire_lease_spec(&spec, "replica-2", now + chrono::Duration::seconds(30))
+        .expect("Expired lease is taken over");
+    assert_eq!(taken_over.holder_identity.as_deref(), Some("replica-2"));
+    assert_eq!(taken_over.lease_transitions, Some(2));
+
+    // The holder renews it
+    let renewed = acquire_lease_spec(&spec, "replica-1", now + chrono::Duration::seconds(5)).expect("Lease is renewed");
+    assert_eq!(renewed.acquire_time, spec.acquire_time);
+    assert_eq!(renewed.lease_transitions, Some(1));
+    assert!(!is_lease_expired(&renewed, now + chrono::Duration::seconds(10)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_leadership_lost() {
+    // Lease held by the API server stand-in, `null` until it is created
+    let lease = Arc::new(Mutex::new(serde_json::Value::Null));
+    let stored_lease = lease.clone();
+    let (client, _) = helpers::fake_api_server(move |request| {
+        let mut lease = stored_lease.lock().unwrap();
+        match request.method.as_str() {
+            "GET" if lease.is_null() => (
+                404,
+                serde_json::json!({"status": "Failure", "message": "not found", "reason": "NotFound", "code": 404})
+                    .to_string(),
+            ),
+            "GET" => (200, lease.to_string()),
+            _ => {
+                *lease = serde_json::from_str(&request.body).unwrap();
+                (200, lease.to_string())
+            }
+        }
+    })
+    .await;
+    let set_holder = |holder: Option<&str>| {
+        let mut lease = lease.lock().unwrap();
+        lease["spec"]["holderIdentity"] = serde_json::json!(holder);
+        lease["spec"]["renewTime"] =
+            serde_json::json!(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true));
+    };
+    let holder = || lease.lock().unwrap()["spec"]["holderIdentity"].clone();
+
+    let leader_election = Arc::new(LeaderElection::new(client, "default", "gordo-controller", "replica-1"));
+    let leader_state = leader_election.state();
+    // Counts the controller runs, and the ones which were stopped
+    let runs = Arc::new(Mutex::new((0, 0)));
+    struct StopGuard(Arc<Mutex<(u32, u32)>>);
+    impl Drop for StopGuard {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().1 += 1;
+        }
+    }
+    let controller_runs = runs.clone();
+    let run_controller = move || {
+        let runs = controller_runs.clone();
+        async move {
+            runs.lock().unwrap().0 += 1;
+            let _guard = StopGuard(runs);
+            futures::future::pending::<()>().await
+        }
+    };
+    let election = leader_election.clone();
+    let controller = tokio::spawn(async move { election.run_while_leader(run_controller).await });
+    // Stand-in for the HTTP API served next to the controller
+    let server = tokio::spawn(futures::future::pending::<()>());
+    let wait_for = |expected: (u32, u32)| {
+        let runs = runs.clone();
+        async move {
+            for _ in 0..100 {
+                if *runs.lock().unwrap() == expected {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            panic!(
+                "Controller runs are {:?}, expected {:?}",
+                runs.lock().unwrap(),
+                expected
+            );
+        }
+    };
+
+    wait_for((1, 0)).await;
+    assert!(leader_state.is_leader());
+    assert_eq!(holder(), "replica-1");
+
+    // Another replica takes the lease over, the controller is stopped but the replica keeps running
+    set_holder(Some("replica-2"));
+    wait_for((1, 1)).await;
+    assert!(!leader_state.is_leader());
+    assert!(!controller.is_finished());
+    assert!(!server.is_finished());
+
+    // It becomes the leader again once the lease is released
+    set_holder(None);
+    wait_for((2, 1)).await;
+    assert!(leader_state.is_leader());
+
+    // Shutting down releases the lease
+    controller.abort();
+    let _ = controller.await;
+    leader_election.release().await;
+    assert!(!leader_state.is_leader());
+    assert!(holder().is_null());
+    server.abort();
+}
+
+#[test]
+fn test_has_cleanup_finalizer() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    assert!(!has_cleanup_finalizer(&gordo));
+    gordo.metadata.finalizers = Some(vec![
+        "example.com/other".to_string(),
+        GORDO_CLEANUP_FINALIZER.to_string(),
+    ]);
+    assert!(has_cleanup_finalizer(&gordo));
+}
+
+#[tokio::test]
+async fn test_cleanup_gordo() {
+    // Workflows and models of the project held by the API server stand-in, and whether the Gordo changed since read
+    let state = Arc::new(Mutex::new((
+        vec![serde_json::json!({"metadata": {"name": "workflow-1"}, "spec": {}})],
+        vec![serde_json::json!({"metadata": {"name": "model-1"}, "spec": {"gordo-version": "1", "config": {}}})],
+        false,
+    )));
+    let server_state = state.clone();
+    let gordo_json = serde_json::to_string(&helpers::deserialize_config::<Gordo>("example-gordo.yaml")).unwrap();
+    let (client, requests) = helpers::fake_api_server(move |request| {
+        let mut state = server_state.lock().unwrap();
+        let (workflows, models, updated) = &mut *state;
+        let resources = if request.path.contains("/workflows") {
+            Some(workflows)
+        } else if request.path.contains("/models") {
+            Some(models)
+        } else {
+            None
+        };
+        match (request.method.as_str(), resources) {
+            ("GET", Some(items)) => (200, serde_json::json!({"metadata": {}, "items": items}).to_string()),
+            ("GET", None) => (200, serde_json::json!({"metadata": {}, "items": []}).to_string()),
+            ("DELETE", Some(items)) => {
+                for item in items.iter_mut() {
+                    item["metadata"]["deletionTimestamp"] = serde_json::json!("2022-05-02T10:21:03Z");
+                }
+                (200, serde_json::json!({"metadata": {}, "items": items}).to_string())
+            }
+            // The `test` operation of the finalizer patch fails after a concurrent update
+            ("PATCH", _) if !request.path.ends_with("/status") && *updated => (
+                422,
+                serde_json::json!({"status": "Failure", "message": "the test operation failed", "reason": "Invalid", "code": 422})
+                    .to_string(),
+            ),
+            _ => (200, gordo_json.clone()),
+        }
+    })
+    .await;
+    let recorder = Recorder::new(client.clone(), None);
+    let gordo_api: Api<Gordo> = Api::namespaced(client.clone(), "default");
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.namespace = Some("default".to_string());
+    gordo.metadata.finalizers = Some(vec![
+        "example.com/other".to_string(),
+        GORDO_CLEANUP_FINALIZER.to_string(),
+    ]);
+    let cleanup = |gordo: Gordo| {
+        let (client, gordo_api, recorder) = (client.clone(), gordo_api.clone(), recorder.clone());
+        async move { cleanup_gordo(&gordo, &client, &gordo_api, "default", &recorder).await }
+    };
+    let take_requests = || std::mem::take(&mut *requests.lock().unwrap());
+    let patched_condition = |requests: &[helpers::ApiRequest]| {
+        let patch = requests
+            .iter()
+            .find(|request| request.method == "PATCH" && request.path.contains("/status"))
+            .expect("The status is patched");
+        let status: serde_json::Value = serde_json::from_str(&patch.body).unwrap();
+        let condition = status["status"]["conditions"][0].clone();
+        (condition["status"].clone(), condition["reason"].clone())
+    };
+    let finalizer_patches = |requests: &[helpers::ApiRequest]| -> Vec<serde_json::Value> {
+        requests
+            .iter()
+            .filter(|request| request.method == "PATCH" && !request.path.contains("/status"))
+            .map(|request| serde_json::from_str(&request.body).unwrap())
+            .collect()
+    };
+    let await_change = format!("{:?}", Action::await_change());
+
+    // The finalizer is kept until the workflows and models are deleted
+    let action = cleanup(gordo.clone()).await.unwrap();
+    assert_ne!(format!("{:?}", action), await_change);
+    let sent = take_requests();
+    let deleted: Vec<&str> = sent
+        .iter()
+        .filter(|request| request.method == "DELETE")
+        .map(|request| request.path.split('?').next().unwrap())
+        .collect();
+    assert_eq!(
+        deleted,
+        vec![
+            "/apis/argoproj.io/v1alpha1/namespaces/default/workflows",
+            "/apis/equinor.com/v1/namespaces/default/models"
+        ]
+    );
+    assert_eq!(patched_condition(&sent), ("False".into(), "CleaningUp".into()));
+    assert!(finalizer_patches(&sent).is_empty());
+
+    // Resources already being deleted aren't deleted again
+    cleanup(gordo.clone()).await.unwrap();
+    let sent = take_requests();
+    assert!(sent.iter().all(|request| request.method != "DELETE"));
+    assert!(finalizer_patches(&sent).is_empty());
+
+    // Released once they are gone, checking that the finalizer is still at the removed index
+    {
+        let mut state = state.lock().unwrap();
+        state.0.clear();
+        state.1.clear();
+    }
+    let action = cleanup(gordo.clone()).await.unwrap();
+    assert_eq!(format!("{:?}", action), await_change);
+    let sent = take_requests();
+    assert_eq!(patched_condition(&sent), ("True".into(), "CleanedUp".into()));
+    assert_eq!(
+        finalizer_patches(&sent),
+        vec![serde_json::json!([
+            {"op": "test", "path": "/metadata/finalizers/1", "value": GORDO_CLEANUP_FINALIZER},
+            {"op": "remove", "path": "/metadata/finalizers/1"},
+        ])]
+    );
+    assert_eq!(sent.last().unwrap().method, "PATCH");
+
+    // Nothing left to do once the finalizer is gone
+    let mut released = gordo.clone();
+    released.metadata.finalizers = Some(vec!["example.com/other".to_string()]);
+    let action = cleanup(released).await.unwrap();
+    assert_eq!(format!("{:?}", action), await_change);
+    assert!(take_requests().is_empty());
+
+    // A concurrent update of the finalizers fails the reconcile, which retries with the updated Gordo
+    state.lock().unwrap().2 = true;
+    match cleanup(gordo.clone()).await {
+        Err(Error::KubeError(kube::Error::Api(response))) => assert_eq!(response.code, 422),
+        other => panic!("Expected the finalizer patch to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_crds_are_up_to_date() {
+    // `make crds` regenerates the manifests after changing the Gordo or Model types
+    for (crd, file_name) in crds()
+        .iter()
+        .zip(["k8s/base/gordo-crd.yaml", "k8s/base/model-crd.yaml"])
+    {
+        let generated = crds_yaml(std::slice::from_ref(crd)).expect("Unable to serialize CRD");
+        let manifest = std::fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file_name))
+            .expect("Failed to read CRD manifest");
+        assert_eq!(generated, manifest, "{} is out of date", file_name);
+        let labels = crd.metadata.labels.as_ref().expect("CRD without labels");
+        assert_eq!(labels.get("stage").map(String::as_str), Some("production"));
+    }
+}
+
+#[test]
+fn test_validate_gordo() {
+    let gordo = |spec: serde_json::Value| -> DynamicObject {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "equinor.com/v1",
+            "kind": "Gordo",
+            "metadata": {"name": "test-project-name", "generation": 1},
+            "spec": spec,
+        }))
+        .unwrap()
+    };
+    let valid: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let valid_spec = serde_json::to_value(&valid.spec).unwrap();
+    assert_eq!(validate_gordo(&gordo(valid_spec.clone())), Vec::<String>::new());
+
+    let mut spec = valid_spec.clone();
+    spec["deploy-version"] = "latest:1".into();
+    spec["config"]["models"][1]["name"] = spec["config"]["models"][0]["name"].clone();
+    spec["config"]["models"][0]["dataset"] = "tags: [TAG-A".into();
+    let errors = validate_gordo(&gordo(spec));
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(errors[0].starts_with("spec.deploy-version 'latest:1'"));
+    assert!(errors[1].starts_with("spec.config.machines[0].dataset is not valid YAML"));
+    assert!(errors[2].ends_with("is duplicated"));
+
+    let mut spec = valid_spec.clone();
+    spec["deploy-environment"]["DEBUG"] = true.into();
+    assert_eq!(
+        validate_gordo(&gordo(spec)),
+        vec!["spec.deploy-environment.DEBUG must be a string or a valueFrom reference, got true"]
+    );
+
+    let mut spec = valid_spec.clone();
+    spec["deploy-environment"]["DL_SERVICE_AUTH_STR"] = serde_json::json!({
+        "valueFrom": {"secretKeyRef": {"name": "dlserviceauth", "key": "tenant_id_secret"}},
+    });
+    assert_eq!(validate_gordo(&gordo(spec.clone())), Vec::<String>::new());
+    spec["deploy-environment"]["DL_SERVICE_AUTH_STR"]["valueFrom"] = serde_json::json!({});
+    assert_eq!(
+        validate_gordo(&gordo(spec)),
+        vec!["spec.deploy-environment.DL_SERVICE_AUTH_STR.valueFrom must have exactly one source, got 0"]
+    );
+
+    let mut spec = valid_spec;
+    spec["config"]["models"][0].as_object_mut().unwrap().remove("name");
+    assert_eq!(
+        validate_gordo(&gordo(spec)),
+        vec!["spec.config.machines[0].name is required"]
+    );
+
+    let mut long_name = gordo(serde_json::to_value(&valid.spec).unwrap());
+    long_name.metadata.name = Some("a".repeat(52));
+    assert_eq!(validate_gordo(&long_name).len(), 1);
+}
+
+#[test]
+fn test_mutate_gordo() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.uid = Some("6571b980-8824-4b4f-b87c-639c40ef91e3".to_string());
+    let config = helpers::config(vec![
+        ("DEPLOY_IMAGE", "ghcr.io/equinor/gordo-deploy"),
+        (
+            "DEFAULT_DEPLOY_ENVIRONMENT",
+            r#"{"WORKFLOW_GENERATOR_DOCKER_REGISTRY": "ghcr.io", "ARGO_LOG_LEVEL": "debug", "API_TOKEN": "s3cr3t",
+                "DB_PASSWORD": {"valueFrom": {"secretKeyRef": {"name": "db", "key": "password"}}}}"#,
+        ),
+    ]);
+    let object: DynamicObject = serde_json::from_value(serde_json::to_value(&gordo).unwrap()).unwrap();
+    let patch = mutate_gordo(&object, &config);
+    // Literal defaults may be credentials, they never end up in the stored Gordo
+    let patch_json = serde_json::to_string(&patch).unwrap();
+    assert!(!patch_json.contains("s3cr3t"));
+    assert!(!patch_json.contains("debug"));
+
+    let mut mutated = serde_json::to_value(&gordo).unwrap();
+    json_patch::patch(&mut mutated, &patch).expect("Unable to apply patch");
+    let mutated: Gordo = serde_json::from_value(mutated).unwrap();
+    assert_eq!(mutated.spec.docker_registry.as_deref(), Some("ghcr.io"));
+    assert_eq!(mutated.spec.deploy_repository.as_deref(), Some("equinor/gordo-deploy"));
+    let environment = mutated.spec.deploy_environment.as_ref().unwrap();
+    // The Gordo's own values are kept, and only the references of the defaults are added
+    assert_eq!(environment["WORKFLOW_GENERATOR_DOCKER_REGISTRY"], "docker.io".into());
+    assert!(matches!(environment["DB_PASSWORD"], DeployEnvValue::ValueFrom { .. }));
+    assert!(!environment.contains_key("ARGO_LOG_LEVEL"));
+    assert!(!environment.contains_key("API_TOKEN"));
+
+    // The deploy Job is unchanged, and doesn't depend on the controller defaults anymore
+    let container = |gordo: &Gordo, config: &Config| {
+        let job = create_deploy_job(gordo, config, "1234").expect("Unable to create deploy job");
+        job.spec.unwrap().template.spec.unwrap().containers[0].clone()
+    };
+    let expected = container(&gordo, &config);
+    assert_eq!(container(&mutated, &config), expected);
+    let env_var = |name: &str| {
+        expected
+            .env
+            .iter()
+            .flatten()
+            .find(|env_var| env_var.name == name)
+            .and_then(|env_var| env_var.value.clone())
+    };
+    assert_eq!(env_var("API_TOKEN").as_deref(), Some("s3cr3t"));
+    let other_config = helpers::config(vec![("DEPLOY_IMAGE", "docker.io/equinor/gordo-deploy")]);
+    assert_eq!(container(&mutated, &other_config).image, expected.image);
+
+    // Applying the defaults again is a no-op
+    let object: DynamicObject = serde_json::from_value(serde_json::to_value(&mutated).unwrap()).unwrap();
+    assert!(mutate_gordo(&object, &config).0.is_empty());
+}
+
+#[test]
+fn test_backoff_delay() {
+    let config = helpers::config(vec![]);
+    assert_eq!(config.reconcile_interval, Duration::from_secs(300));
+    let backoff = BackoffConfig {
+        jitter: 0.0,
+        ..config.reconcile_backoff
+    };
+    assert_eq!(backoff.delay(1, 0.5), Duration::from_secs(5));
+    assert_eq!(backoff.delay(2, 0.5), Duration::from_secs(10));
+    assert_eq!(backoff.delay(5, 0.5), Duration::from_secs(80));
+    assert_eq!(backoff.delay(100, 0.5), Duration::from_secs(600));
+
+    let backoff = BackoffConfig { jitter: 0.2, ..backoff };
+    assert_eq!(backoff.delay(3, 0.0), Duration::from_secs(16));
+    assert_eq!(backoff.delay(3, 0.5), Duration::from_secs(20));
+    assert_eq!(backoff.delay(3, 1.0), Duration::from_secs(24));
+    // The jitter never goes above the cap
+    assert_eq!(backoff.delay(100, 1.0), Duration::from_secs(600));
+
+    let config = helpers::config(vec![("RECONCILE_BACKOFF_BASE", "1"), ("RECONCILE_BACKOFF_MAX", "60")]);
+    assert_eq!(config.reconcile_backoff.base, Duration::from_secs(1));
+    assert_eq!(config.reconcile_backoff.max, Duration::from_secs(60));
+    assert!(Config::from_envs(
+        vec![
+            ("DEPLOY_IMAGE".to_string(), "gordo-deploy".to_string()),
+            ("DOCKER_REGISTRY".to_string(), "ghcr.io".to_string()),
+            ("DEFAULT_DEPLOY_ENVIRONMENT".to_string(), "".to_string()),
+            ("RESOURCES_LABELS".to_string(), "".to_string()),
+            ("RECONCILE_BACKOFF_JITTER".to_string(), "1.5".to_string()),
+        ]
+        .into_iter()
+    )
+    .is_err());
+}
+
+#[test]
+fn test_backoffs() {
+    let config = BackoffConfig {
+        base: Duration::from_secs(60),
+        max: Duration::from_secs(600),
+        jitter: 0.0,
+    };
+    let backoffs = Backoffs::default();
+    let key = "Gordo.v1.equinor.com/test-project-name.default";
+    let transient = || Error::NotReady("cache");
+    assert!(backoffs.pending(key, Some(1)).is_none());
+
+    let entry = backoffs.failed(key, Some(1), &config, &transient());
+    assert_eq!(entry.failures, 1);
+    assert_eq!(entry.retry_in, Some(Duration::from_secs(60)));
+    let entry = backoffs.failed(key, Some(1), &config, &transient());
+    assert_eq!(entry.failures, 2);
+    assert_eq!(entry.retry_in, Some(Duration::from_secs(120)));
+    let retry_in = backoffs.pending(key, Some(1)).unwrap().retry_in.unwrap();
+    assert!(retry_in > Duration::from_secs(60) && retry_in <= Duration::from_secs(120));
+
+    // A new generation is reconciled right away, and starts over
+    assert!(backoffs.pending(key, Some(2)).is_none());
+    assert_eq!(backoffs.failed(key, Some(2), &config, &transient()).failures, 1);
+
+    // Permanent errors wait for a change of the object
+    let entry = backoffs.failed(key, Some(2), &config, &Error::MissingKey(".metadata.namespace"));
+    assert_eq!(entry.retry_in, None);
+    assert_eq!(backoffs.pending(key, Some(2)).unwrap().retry_in, None);
+
+    assert!(backoffs.succeeded(key));
+    assert!(!backoffs.succeeded(key));
+    assert!(backoffs.pending(key, Some(2)).is_none());
+
+    // Deleted objects are forgotten
+    let not_found = Error::KubeError(kube::Error::Api(kube::error::ErrorResponse {
+        status: "Failure".to_string(),
+        message: "gordos.equinor.com \"test-project-name\" not found".to_string(),
+        reason: "NotFound".to_string(),
+        code: 404,
+    }));
+    assert!(not_found.is_not_found());
+    assert!(!transient().is_not_found());
+    backoffs.failed(key, Some(2), &config, &transient());
+    backoffs.forget(key);
+    assert!(backoffs.pending(key, Some(2)).is_none());
+}
+
+#[test]
+fn test_model_retries() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let mut status = ModelStatus {
+        phase: ModelPhase::Failed,
+        error_type: Some("DataLakeTimeoutError".to_string()),
+        ..ModelStatus::default()
+    };
+    // No retries by default
+    assert!(!should_retry_model(&status, &gordo));
+
+    gordo.spec.max_model_retries = Some(2);
+    assert!(should_retry_model(&status, &gordo));
+    gordo.spec.model_retry_error_types = Some(vec!["DataLakeTimeoutError".to_string()]);
+    assert!(should_retry_model(&status, &gordo));
+    gordo.spec.model_retry_error_types = Some(vec!["ValueError".to_string()]);
+    assert!(!should_retry_model(&status, &gordo));
+    gordo.spec.model_retry_error_types = None;
+    status.retries = 2;
+    assert!(!should_retry_model(&status, &gordo));
+    status.retries = 1;
+    status.phase = ModelPhase::Succeeded;
+    assert!(!should_retry_model(&status, &gordo));
+
+    // Changing the retry settings doesn't redeploy the project
+    let default_gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    assert_eq!(get_revision(&gordo.spec, 0), get_revision(&default_gordo.spec, 0));
+
+    let workflow: DynamicObject = serde_json::from_value(serde_json::json!({
+        "apiVersion": "argoproj.io/v1alpha1",
+        "kind": "Workflow",
+        "metadata": {
+            "name": "test-project-name-1234-0",
+            "namespace": "default",
+            "labels": {PROJECT_NAME_LABEL: "test-project-name", WF_NUMBER_LABEL: "0"},
+            "ownerReferences": [{"apiVersion": "equinor.com/v1", "kind": "Gordo", "name": "test-project-name", "uid": "6571b980"}],
+        },
+        "spec": {"entrypoint": "do-all"},
+        "status": {"phase": "Failed"},
+    }))
+    .unwrap();
+    let retry = retry_workflow(&workflow, 1);
+    assert_eq!(retry.metadata.name.as_deref(), Some("test-project-name-1234-0-retry-1"));
+    assert_eq!(retry.metadata.labels.as_ref().unwrap()[WF_RETRY_LABEL], "1");
+    assert_eq!(retry.metadata.labels.as_ref().unwrap()[WF_NUMBER_LABEL], "0");
+    assert_eq!(retry.metadata.owner_references, workflow.metadata.owner_references);
+    assert_eq!(retry.data, serde_json::json!({"spec": {"entrypoint": "do-all"}}));
+    // Retries of retries are named after the original workflow
+    let retry = retry_workflow(&retry, 2);
+    assert_eq!(retry.metadata.name.as_deref(), Some("test-project-name-1234-0-retry-2"));
+
+    // A retried model follows the workflows of its retry
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.labels = Some(BTreeMap::from([(WF_NUMBER_LABEL.to_string(), "0".to_string())]));
+    model.status = Some(ModelStatus {
+        retries: 1,
+        ..ModelStatus::default()
+    });
+    let workflow = |retry: Option<&str>| {
+        let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+        let mut labels = BTreeMap::from([(WF_NUMBER_LABEL.to_string(), "0".to_string())]);
+        if let Some(retry) = retry {
+            labels.insert(WF_RETRY_LABEL.to_string(), retry.to_string());
+        }
+        workflow.metadata.labels = Some(labels);
+        workflow
+    };
+    let workflows = vec![workflow(None), workflow(Some("1"))];
+    let model_workflows = find_model_workflows(&model, &workflows);
+    assert_eq!(model_workflows.len(), 1);
+    assert_eq!(workflow_retry(model_workflows[0]), 1);
+}
+
+#[test]
+fn test_model_failed_in_running_workflow() {
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.labels = Some(BTreeMap::from([
+        (WF_NUMBER_LABEL.to_string(), "0".to_string()),
+        (
+            "applications.gordo.equinor.com/model-name".to_string(),
+            "model-1".to_string(),
+        ),
+    ]));
+    let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+    workflow.metadata.labels = Some(BTreeMap::from([(WF_NUMBER_LABEL.to_string(), "0".to_string())]));
+    workflow.status = Some(ArgoWorkflowStatus {
+        phase: Some(ArgoWorkflowPhase::Running),
+        nodes: BTreeMap::from([(
+            "wf-1".to_string(),
+            ArgoNodeStatus {
+                display_name: "model-builder-model-1".to_string(),
+                template_name: Some(MODEL_BUILDER_TEMPLATE.to_string()),
+                phase: Some(ArgoWorkflowPhase::Failed),
+                ..ArgoNodeStatus::default()
+            },
+        )]),
+    });
+    let workflows = vec![workflow];
+
+    // The model failed, but its workflow is still building the other models and can't be resubmitted yet
+    let model_workflows = find_model_workflows(&model, &workflows);
+    assert_eq!(
+        model_build_phase(find_model_build_node(&model, &model_workflows), &model_workflows),
+        Some(ModelPhase::Failed)
+    );
+    assert!(!is_failed_workflow(&workflows[0]));
+
+    // A model without workflows for its retry is not built yet
+    model.status = Some(ModelStatus {
+        retries: 1,
+        ..ModelStatus::default()
+    });
+    let model_workflows = find_model_workflows(&model, &workflows);
+    assert!(model_workflows.is_empty());
+    assert_eq!(model_build_phase(None, &model_workflows), None);
+}
+
+#[test]
+fn test_requested_rebuild() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let annotate = |gordo: &mut Gordo, requested_at: &str| {
+        gordo.metadata.annotations = Some(BTreeMap::from([(
+            REBUILD_REQUESTED_AT_ANNOTATION.to_string(),
+            requested_at.to_string(),
+        )]));
+    };
+    annotate(&mut gordo, "2020-01-01T00:00:00Z");
+    // The first deploy isn't a rebuild, and records the request as handled
+    assert_eq!(requested_rebuild(&gordo), None);
+    gordo.status = Some(GordoStatus::from(&gordo));
+    assert_eq!(
+        gordo.status.as_ref().unwrap().rebuild_requested_at.as_deref(),
+        Some("2020-01-01T00:00:00Z")
+    );
+    assert_eq!(requested_rebuild(&gordo), None);
+
+    annotate(&mut gordo, "2020-01-02T00:00:00Z");
+    assert_eq!(requested_rebuild(&gordo), Some("2020-01-02T00:00:00Z"));
+    gordo.status = Some(GordoStatus::from(&gordo));
+    assert_eq!(requested_rebuild(&gordo), None);
+
+    // Removing the annotation doesn't forget the last rebuild
+    gordo.metadata.annotations = None;
+    assert_eq!(requested_rebuild(&gordo), None);
+    assert_eq!(
+        GordoStatus::from(&gordo).rebuild_requested_at.as_deref(),
+        Some("2020-01-02T00:00:00Z")
+    );
+}
+
+#[test]
+fn test_suspend() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    let default_gordo = gordo.clone();
+    assert!(!is_suspended(&gordo));
+    assert!(!has_suspended_condition(&gordo));
+
+    gordo.spec.suspend = Some(true);
+    assert!(is_suspended(&gordo));
+    // Suspending and resuming doesn't redeploy the project
+    assert_eq!(get_revision(&gordo.spec, 0), get_revision(&default_gordo.spec, 0));
+
+    let mut status = GordoStatus::default();
+    set_condition(
+        &mut status.conditions,
+        Condition::from_bool(CONDITION_SUSPENDED, true, "Suspended", "", None),
+    );
+    gordo.status = Some(status);
+    assert!(has_suspended_condition(&gordo));
+
+    let workflow = |phase: Option<ArgoWorkflowPhase>| {
+        let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+        workflow.status = Some(ArgoWorkflowStatus {
+            phase,
+            ..ArgoWorkflowStatus::default()
+        });
+        workflow
+    };
+    assert!(is_unfinished_workflow(&workflow(None)));
+    assert!(is_unfinished_workflow(&workflow(Some(ArgoWorkflowPhase::Running))));
+    assert!(!is_unfinished_workflow(&workflow(Some(ArgoWorkflowPhase::Succeeded))));
+    assert!(!is_unfinished_workflow(&workflow(Some(ArgoWorkflowPhase::Failed))));
+}
+
+#[test]
+fn test_revision_retention() {
+    let now = Utc::now();
+    let revisions: HashMap<String, chrono::DateTime<Utc>> =
+        vec![("current", 0), ("1-day", 1), ("2-days", 2), ("3-days", 3)]
+            .into_iter()
+            .map(|(revision, days)| (revision.to_string(), now - chrono::Duration::days(days)))
+            .collect();
+    let expired = |retention: RevisionRetention, current_revision: &str| {
+        let mut expired = expired_revisions(&retention, current_revision, &revisions, now);
+        expired.sort();
+        expired
+    };
+
+    // Everything is kept by default
+    assert!(expired(RevisionRetention::default(), "current").is_empty());
+    let keep_revisions = |keep_revisions| RevisionRetention {
+        keep_revisions: Some(keep_revisions),
+        ..RevisionRetention::default()
+    };
+    assert_eq!(expired(keep_revisions(2), "current"), vec!["2-days", "3-days"]);
+    assert_eq!(expired(keep_revisions(1), "current"), vec!["1-day", "2-days", "3-days"]);
+    // The current revision is never deleted, and counts as the latest one
+    assert_eq!(expired(keep_revisions(0), "current"), vec!["1-day", "2-days", "3-days"]);
+    assert_eq!(expired(keep_revisions(2), "3-days"), vec!["1-day", "2-days"]);
+    assert_eq!(
+        expired(keep_revisions(2), "not-created-yet"),
+        vec!["1-day", "2-days", "3-days"]
+    );
+
+    let max_age = RevisionRetention {
+        max_age_seconds: Some(36 * 3600),
+        ..RevisionRetention::default()
+    };
+    assert_eq!(expired(max_age.clone(), "current"), vec!["2-days", "3-days"]);
+    // A revision is kept if any rule keeps it
+    let both = RevisionRetention {
+        keep_revisions: Some(3),
+        ..max_age.clone()
+    };
+    assert_eq!(expired(both, "current"), vec!["3-days"]);
+
+    // The Gordo overrides the controller's policy field by field
+    let config = helpers::config(vec![(
+        "REVISION_RETENTION",
+        r#"{"keep-revisions": 5, "max-age-seconds": 60}"#,
+    )]);
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    assert_eq!(revision_retention(&gordo, &config), config.revision_retention);
+    gordo.spec.revision_retention = Some(keep_revisions(2));
+    assert_eq!(
+        revision_retention(&gordo, &config),
+        RevisionRetention {
+            keep_revisions: Some(2),
+            max_age_seconds: Some(60),
+        }
+    );
+}
+
+#[test]
+fn test_revision_history() {
+    let entry = |revision: &str| RevisionHistoryEntry {
+        revision: revision.to_string(),
+        deploy_version: "0.1.0".to_string(),
+        ..RevisionHistoryEntry::default()
+    };
+    let revisions =
+        |status: &GordoStatus| -> Vec<String> { status.history.iter().map(|entry| entry.revision.clone()).collect() };
+    let mut status = GordoStatus::default();
+    for revision in &["1", "2", "3", "4"] {
+        status.record_revision(entry(revision), 3);
+    }
+    // Only the latest revisions are kept
+    assert_eq!(revisions(&status), vec!["2", "3", "4"]);
+    status.record_revision(entry("3"), 3);
+    assert_eq!(revisions(&status), vec!["2", "4", "3"]);
+
+    status.project_revision = "3".to_string();
+    status.current_history_entry().unwrap().n_models_built = 2;
+    assert_eq!(status.history[2].n_models_built, 2);
+
+    // Rolling back moves the revision to the end of the history
+    let rolled_back = rolled_back_status(&status, "2");
+    assert_eq!(rolled_back.project_revision, "2");
+    assert_eq!(revisions(&rolled_back), vec!["4", "3", "2"]);
+
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.status = Some(status);
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.labels = Some(BTreeMap::from([(
+        "applications.gordo.equinor.com/project-revision".to_string(),
+        "2".to_string(),
+    )]));
+    let models = vec![model];
+    assert!(check_rollback(&gordo, &models, "2").is_ok());
+    // The current revision, revisions missing from the history and ones without models can't be rolled back to
+    assert!(check_rollback(&gordo, &models, "3").is_err());
+    assert!(check_rollback(&gordo, &models, "1").is_err());
+    assert!(check_rollback(&gordo, &models, "4").is_err());
+}
+
+#[test]
+fn test_model_build_phase() {
+    let node = |display_name: &str, phase: ArgoWorkflowPhase| ArgoNodeStatus {
+        display_name: display_name.to_string(),
+        template_name: Some(MODEL_BUILDER_TEMPLATE.to_string()),
+        phase: Some(phase),
+        started_at: Some("2020-01-01T00:00:00Z".to_string()),
+        ..ArgoNodeStatus::default()
+    };
+    let workflow: Workflow = serde_json::from_value(serde_json::json!({
+        "apiVersion": "argoproj.io/v1alpha1",
+        "kind": "Workflow",
+        "metadata": {"name": "test-project-name-1234-0"},
+        "spec": {},
+        "status": {
+            "phase": "Failed",
+            "nodes": {
+                "wf-1": {"displayName": "model-builder-model-1", "templateName": "model-builder", "phase": "Succeeded"},
+                "wf-2": {"displayName": "model-builder-model-2", "templateName": "model-builder", "phase": "Failed",
+                         "message": "OOMKilled"},
+                "wf-3": {"displayName": "model-builder-model-2(0)", "templateName": "model-builder", "phase": "Failed"},
+                "wf-4": {"displayName": "model-builder-model-3", "templateName": "model-builder", "phase": "Omitted"},
+            },
+        },
+    }))
+    .unwrap();
+
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    let workflows = vec![&workflow];
+    let build_phase = |model: &Model| model_build_phase(find_model_build_node(model, &workflows), &workflows);
+    let set_model_name = |model: &mut Model, model_name: &str| {
+        model.metadata.labels = Some(BTreeMap::from([(
+            "applications.gordo.equinor.com/model-name".to_string(),
+            model_name.to_string(),
+        )]));
+    };
+    // Each model of a failed workflow gets the phase of its own step
+    set_model_name(&mut model, "model-1");
+    assert_eq!(build_phase(&model), Some(ModelPhase::Succeeded));
+    set_model_name(&mut model, "model-2");
+    assert_eq!(
+        find_model_build_node(&model, &workflows).unwrap().message.as_deref(),
+        Some("OOMKilled")
+    );
+    assert_eq!(build_phase(&model), Some(ModelPhase::Failed));
+    // The step won't run anymore
+    set_model_name(&mut model, "model-3");
+    assert_eq!(build_phase(&model), Some(ModelPhase::Failed));
+    // Without a step, the phase of the workflows is used
+    set_model_name(&mut model, "model-4");
+    assert_eq!(find_model_build_node(&model, &workflows), None);
+    assert_eq!(build_phase(&model), Some(ModelPhase::Failed));
+
+    // A running step of a running workflow is still being built
+    let running = node("model-builder-model-1", ArgoWorkflowPhase::Running);
+    let mut workflow = Workflow::new("workflow", ArgoWorkflowSpec::default());
+    workflow.status = Some(ArgoWorkflowStatus {
+        phase: Some(ArgoWorkflowPhase::Running),
+        ..ArgoWorkflowStatus::default()
+    });
+    assert!(is_model_build_node(&running, "model-1"));
+    assert!(!is_model_build_node(&running, "model-11"));
+    assert_eq!(model_build_phase(Some(&running), &vec![&workflow]), None);
+    assert_eq!(model_build_phase(None, &vec![&workflow]), None);
+}
+
+#[test]
+fn test_gordo_metrics() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.name = Some("metrics-project".to_string());
+    gordo.metadata.namespace = Some("metrics".to_string());
+    gordo.status = Some(GordoStatus {
+        project_revision: "1234".to_string(),
+        n_models_built: 1,
+        ..GordoStatus::default()
+    });
+    let model = |phase: ModelPhase, revision: &str| {
+        let mut model: Model = helpers::deserialize_config("example-model.yaml");
+        model.metadata.owner_references.as_mut().unwrap()[0].name = "metrics-project".to_string();
+        model.metadata.labels = Some(BTreeMap::from([(
+            "applications.gordo.equinor.com/project-revision".to_string(),
+            revision.to_string(),
+        )]));
+        model.status = Some(ModelStatus {
+            phase,
+            ..ModelStatus::default()
+        });
+        model
+    };
+    let models = vec![
+        model(ModelPhase::Succeeded, "1234"),
+        model(ModelPhase::Failed, "1234"),
+        model(ModelPhase::Failed, "1234"),
+        // Models of previous revisions are left out
+        model(ModelPhase::Failed, "1233"),
+    ];
+    update_gordo_metrics(&gordo, &models);
+    let n_models = |phase: &str| MODELS.with_label_values(&["metrics-project", "metrics", phase]).get();
+    assert_eq!(n_models("Failed"), 2);
+    assert_eq!(n_models("Succeeded"), 1);
+    assert_eq!(n_models("InProgress"), 0);
+    assert_eq!(
+        GORDO_MODELS_EXPECTED
+            .with_label_values(&["metrics-project", "metrics"])
+            .get(),
+        gordo.spec.config.n_models() as i64
+    );
+    assert_eq!(
+        GORDO_MODELS_BUILT
+            .with_label_values(&["metrics-project", "metrics"])
+            .get(),
+        1
+    );
+
+    remove_gordo_metrics("metrics-project", "metrics");
+    assert!(MODELS
+        .remove_label_values(&["metrics-project", "metrics", "Failed"])
+        .is_err());
+    assert!(GORDO_MODELS_BUILT
+        .remove_label_values(&["metrics-project", "metrics"])
+        .is_err());
+}
+
+#[test]
+fn test_timing_metrics() {
+    assert_eq!(api_resource("/api/v1/namespaces/default/pods/pod-1/log"), "pods");
+    assert_eq!(api_resource("/api/v1/namespaces/default/events"), "events");
+    assert_eq!(api_resource("/api/v1/namespaces/default"), "namespaces");
+    assert_eq!(
+        api_resource("/apis/equinor.com/v1/namespaces/default/gordos/gordo-1/status"),
+        "gordos"
+    );
+    assert_eq!(api_resource("/apis/argoproj.io/v1alpha1/workflows"), "workflows");
+    assert_eq!(api_resource("/version"), "other");
+
+    assert_eq!(
+        seconds_between("2022-01-01T10:00:00Z", "2022-01-01T10:01:30.500Z"),
+        Some(90.5)
+    );
+    assert_eq!(seconds_between("2022-01-01T10:00:00Z", "not a time"), None);
+
+    let mut model: Model = helpers::deserialize_config("example-model.yaml");
+    model.metadata.labels = Some(BTreeMap::from([(
+        PROJECT_NAME_LABEL.to_string(),
+        "timing-project".to_string(),
+    )]));
+    let status = |phase: ModelPhase| ModelStatus {
+        phase,
+        started_at: Some("2022-01-01T10:00:00Z".to_string()),
+        finished_at: Some("2022-01-01T10:10:00Z".to_string()),
+        ..ModelStatus::default()
+    };
+    let histogram = |phase: &str| MODEL_BUILD_DURATION.with_label_values(&["timing-project", phase]);
+    // Only the builds which reached a final phase are observed
+    observe_model_build(&model, &status(ModelPhase::InProgress));
+    observe_model_build(&model, &status(ModelPhase::Succeeded));
+    assert_eq!(histogram("InProgress").get_sample_count(), 0);
+    assert_eq!(histogram("Succeeded").get_sample_count(), 1);
+    assert_eq!(histogram("Succeeded").get_sample_sum(), 600.0);
+
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.status = Some(GordoStatus {
+        project_revision: "1234".to_string(),
+        ..GordoStatus::default()
+    });
+    let revision_model = |revision: &str, created_at: &str| {
+        let mut model: Model = helpers::deserialize_config("example-model.yaml");
+        model.metadata.owner_references.as_mut().unwrap()[0].name = gordo.metadata.name.clone().unwrap();
+        model.metadata.labels = Some(BTreeMap::from([(
+            "applications.gordo.equinor.com/project-revision".to_string(),
+            revision.to_string(),
+        )]));
+        model.metadata.creation_timestamp = Some(Time(created_at.parse().unwrap()));
+        model
+    };
+    assert_eq!(first_model_created_at(&gordo, &[]), None);
+    let models = vec![
+        revision_model("1234", "2022-01-01T10:05:00Z"),
+        revision_model("1234", "2022-01-01T10:03:00Z"),
+        // Models of previous revisions are left out
+        revision_model("1233", "2022-01-01T09:00:00Z"),
+    ];
+    assert_eq!(
+        first_model_created_at(&gordo, &models),
+        Some("2022-01-01T10:03:00Z".parse().unwrap())
+    );
+}
+
+#[derive(Clone, Default)]
+struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut *self.0.lock().unwrap(), buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_telemetry_config() {
+    let config = helpers::config(vec![]);
+    assert_eq!(config.log_format, LogFormat::Text);
+    assert_eq!(config.otlp_endpoint, None);
+
+    let config = helpers::config(vec![("LOG_FORMAT", "json"), ("OTLP_ENDPOINT", "http://localhost:4318")]);
+    assert_eq!(config.log_format, LogFormat::Json);
+    assert_eq!(config.otlp_endpoint.as_deref(), Some("http://localhost:4318"));
+    assert!(Config::from_envs(
+        vec![
+            ("DEPLOY_IMAGE".to_string(), "gordo-deploy".to_string()),
+            ("DOCKER_REGISTRY".to_string(), "ghcr.io".to_string()),
+            ("DEFAULT_DEPLOY_ENVIRONMENT".to_string(), "".to_string()),
+            ("RESOURCES_LABELS".to_string(), "".to_string()),
+            ("LOG_FORMAT".to_string(), "xml".to_string()),
+        ]
+        .into_iter()
+    )
+    .is_err());
+}
+
+#[test]
+fn test_json_logs() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.namespace = Some("default".to_string());
+    gordo.status = Some(GordoStatus {
+        project_revision: "1234".to_string(),
+        ..GordoStatus::default()
+    });
+    let writer = SharedWriter::default();
+    let logs = writer.0.clone();
+    // The tracer only holds a weak reference to its provider
+    let provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+    let tracer = provider.tracer("test");
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .with(json_fmt_layer(move || writer.clone(), helpers::config(vec![]).redactor));
+    tracing::subscriber::with_default(subscriber, || {
+        reconcile_gordo_span(&gordo).in_scope(|| tracing::info!(api_token = "s3cr3t", attempt = 2, "Reconciled"));
+    });
+
+    let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+    assert!(!logs.contains("s3cr3t"));
+    // One object per line, with the fields of its spans
+    let log: serde_json::Value = serde_json::from_str(logs.lines().next().unwrap()).unwrap();
+    assert_eq!(log["level"], "INFO");
+    assert_eq!(log["target"], "test_controller");
+    assert_eq!(log["message"], "Reconciled");
+    assert_eq!(log["attempt"], 2);
+    assert_eq!(log["api_token"], REDACTED);
+    assert_eq!(log["span"], "reconcile_gordo");
+    assert_eq!(log["gordo"], gordo.metadata.name.clone().unwrap());
+    assert_eq!(log["namespace"], "default");
+    assert_eq!(log["revision"], "1234");
+    assert_eq!(log["trace_id"].as_str().unwrap().len(), 32);
+}
+
+#[test]
+fn test_text_logs() {
+    let writer = SharedWriter::default();
+    let logs = writer.0.clone();
+    let subscriber = tracing_subscriber::registry()
+        .with(text_fmt_layer(move || writer.clone(), helpers::config(vec![]).redactor).with_ansi(false));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("deploy", STORAGE_KEY = "s3cr3t", gordo = "project")
+            .in_scope(|| tracing::info!(client_secret = "s3cr3t", attempt = 2, "Deploying"));
+    });
+
+    let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+    assert!(!logs.contains("s3cr3t"));
+    assert!(logs.contains(&format!("deploy{{STORAGE_KEY={} gordo=\"project\"}}", REDACTED)));
+    assert!(logs.contains(&format!("Deploying client_secret={} attempt=2", REDACTED)));
+}
+
+#[test]
+fn test_redactor() {
+    let redactor = Redactor::new(&Config::load_from_list(DEFAULT_REDACT_PATTERNS));
+    assert!(redactor.is_secret("STORAGE_KEY"));
+    assert!(redactor.is_secret("storage_key"));
+    assert!(redactor.is_secret("CLIENT_SECRET_ID"));
+    assert!(redactor.is_secret("apiToken"));
+    assert!(!redactor.is_secret("KEY_VAULT"));
+    assert!(!redactor.is_secret("ARGO_SERVICE_ACCOUNT"));
+    assert_eq!(redactor.redact("ACCESS_KEY", "s3cr3t"), REDACTED);
+    assert_eq!(redactor.redact("ACCESS", "public"), "public");
+    let redactor = Redactor::new(&["DB_*_PASSWORD".to_string()]);
+    assert!(redactor.is_secret("DB_MAIN_PASSWORD"));
+    assert!(!redactor.is_secret("DB_PASSWORD_HINT"));
+    assert!(!Redactor::default().is_secret("API_TOKEN"));
+
+    // Logged specs and patches
+    let redactor = helpers::config(vec![]).redactor;
+    let mut value = serde_json::json!([
+        {"op": "add", "path": "/spec/deploy-environment/API_TOKEN", "value": "s3cr3t"},
+        {"op": "add", "path": "/spec/deploy-environment", "value": {"CLIENT_SECRET": "s3cr3t", "DEBUG": "true"}},
+        {"env": [{"name": "STORAGE_KEY", "value": "s3cr3t"}, {"name": "DEBUG", "value": "true"}]},
+    ]);
+    redactor.redact_json(&mut value);
+    assert_eq!(
+        value,
+        serde_json::json!([
+            {"op": "add", "path": "/spec/deploy-environment/API_TOKEN", "value": REDACTED},
+            {"op": "add", "path": "/spec/deploy-environment", "value": {"CLIENT_SECRET": REDACTED, "DEBUG": "true"}},
+            {"env": [{"name": "STORAGE_KEY", "value": REDACTED}, {"name": "DEBUG", "value": "true"}]},
+        ])
+    );
+
+    let config = helpers::config(vec![
+        (
+            "DEFAULT_DEPLOY_ENVIRONMENT",
+            r#"{"STORAGE_KEY": "s3cr3t", "ARGO_SERVICE_ACCOUNT": "workflow-runner"}"#,
+        ),
+        ("WORKFLOW_GENERATOR_AUTH_TOKEN", "s3cr3t"),
+        ("WORKFLOW_GENERATOR_DOCKER_REGISTRY", "ghcr.io"),
+    ]);
+    let debug = format!("{:?}", config);
+    assert!(!debug.contains("s3cr3t"));
+    assert!(debug.contains("workflow-runner"));
+    assert!(debug.contains("ghcr.io"));
+    // The unmasked values are still used
+    assert_eq!(
+        config.default_deploy_environment.unwrap()["STORAGE_KEY"],
+        DeployEnvValue::Value("s3cr3t".to_string())
+    );
+
+    let config = helpers::config(vec![
+        ("REDACT_PATTERNS", "*_PASSWORD"),
+        ("WORKFLOW_GENERATOR_DB_PASSWORD", "s3cr3t"),
+    ]);
+    assert!(!format!("{:?}", config).contains("s3cr3t"));
+    assert!(!config.redactor.is_secret("API_TOKEN"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_otlp_export() {
+    // Stand-in for the OTLP collector, answering the first export
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let collector = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let request = helpers::read_http_request(&mut socket).await;
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&request).to_string()
+    });
+
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.namespace = Some("otlp-namespace".to_string());
+    let tracer = otlp_tracer(&endpoint).unwrap();
+    let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::with_default(subscriber, || {
+        reconcile_gordo_span(&gordo).in_scope(|| tracing::info!("Reconciled"));
+    });
+    // Exports the batched spans
+    tokio::task::spawn_blocking(shutdown_telemetry).await.unwrap();
+
+    let request = tokio::time::timeout(Duration::from_secs(10), collector)
+        .await
+        .expect("No span exported")
+        .unwrap();
+    assert!(request.starts_with("POST /v1/traces"));
+    assert!(request.contains("reconcile_gordo"));
+    assert!(request.contains("otlp-namespace"));
+    assert!(request.contains(SERVICE_NAME));
+}
@@ -1,9 +1,15 @@
+use gordo_controller::cache::{Cache, IndexedStore};
+use gordo_controller::crd::gordo::gordo::{GordoStatus, RevisionHistoryEntry};
+use gordo_controller::leader::LeaderState;
 use gordo_controller::views::AppState;
+use std::convert::TryFrom;
+use std::time::Duration;
 
 use actix_web::web::Json;
-use actix_web::{http::StatusCode, test, web};
+use actix_web::{http::StatusCode, test, web, ResponseError};
 use gordo_controller::views;
 use gordo_controller::{crd::gordo::Gordo, crd::model::Model};
+use kube::runtime::watcher;
 
 mod helpers;
 
@@ -14,6 +20,67 @@ async fn test_view_health() {
     assert_eq!(resp.status(), StatusCode::OK);
 }
 
+#[tokio::test]
+async fn test_view_health_role() {
+    let leader_state = LeaderState::default();
+    let req = test::TestRequest::default()
+        .app_data(web::Data::new(leader_state.clone()))
+        .to_http_request();
+    let resp = views::health(req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+    assert_eq!(body, r#"{"role":"follower"}"#);
+
+    leader_state.set_leader(true);
+    let req = test::TestRequest::default()
+        .app_data(web::Data::new(leader_state))
+        .to_http_request();
+    let body = actix_web::body::to_bytes(views::health(req).await.into_body())
+        .await
+        .unwrap();
+    assert_eq!(body, r#"{"role":"leader"}"#);
+}
+
+#[tokio::test]
+async fn test_view_validate_gordo() {
+    let review = |deploy_version: &str| {
+        let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+        gordo.spec.deploy_version = deploy_version.to_string();
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "kind": {"group": "equinor.com", "version": "v1", "kind": "Gordo"},
+                "resource": {"group": "equinor.com", "version": "v1", "resource": "gordos"},
+                "name": "test-project-name",
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": {},
+                "object": gordo,
+                "dryRun": false,
+            },
+        }))
+        .unwrap()
+    };
+    let response = |resp: actix_web::HttpResponse| async {
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let review: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        review["response"].clone()
+    };
+
+    let allowed = response(views::validate_gordo(Json(review("latest"))).await).await;
+    assert_eq!(allowed["uid"], "705ab4f5-6393-11e8-b7cc-42010a800002");
+    assert_eq!(allowed["allowed"], true);
+
+    let denied = response(views::validate_gordo(Json(review(""))).await).await;
+    assert_eq!(denied["allowed"], false);
+    assert_eq!(
+        denied["status"]["message"],
+        "spec.deploy-version '' is not a valid image tag"
+    );
+}
+
 #[tokio::test]
 async fn test_view_gordos() {
     let data = app_state().await;
@@ -32,8 +99,171 @@ async fn test_view_models() {
     assert_eq!(resp.0.len(), 0);
 }
 
+#[tokio::test]
+async fn test_view_gordos_namespaces() {
+    let mut gordos: Vec<Gordo> = vec![];
+    for namespace in ["default", "project-a"] {
+        let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+        gordo.metadata.namespace = Some(namespace.to_string());
+        gordos.push(gordo);
+    }
+    let data = cached_app_state(gordos).await;
+    let namespaces = |gordos: Vec<Gordo>| -> Vec<String> {
+        let mut namespaces: Vec<String> = gordos
+            .into_iter()
+            .filter_map(|gordo| gordo.metadata.namespace)
+            .collect();
+        namespaces.sort();
+        namespaces
+    };
+
+    // The routes which aren't namespace-qualified stay in the controller's own namespace
+    let req = test::TestRequest::default().to_http_request();
+    let resp = views::gordos(data.clone(), req).await.unwrap();
+    assert_eq!(namespaces(resp.0), vec!["default"]);
+
+    let req = test::TestRequest::default().to_http_request();
+    let resp = views::all_gordos(data.clone(), req).await.unwrap();
+    assert_eq!(namespaces(resp.0), vec!["default", "project-a"]);
+
+    let resp = views::namespaced_gordos(data.clone(), web::Path::from("project-a".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(namespaces(resp.0), vec!["project-a"]);
+}
+
+#[tokio::test]
+async fn test_view_rollback_refused() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.namespace = Some("default".to_string());
+    gordo.status = Some(GordoStatus {
+        project_revision: "2".to_string(),
+        history: vec![RevisionHistoryEntry {
+            revision: "2".to_string(),
+            ..RevisionHistoryEntry::default()
+        }],
+        ..GordoStatus::default()
+    });
+    let data = cached_app_state(vec![gordo]).await;
+    let rollback = |name: &str, revision: &str| {
+        views::rollback_namespaced_gordo(
+            data.clone(),
+            web::Path::from(("default".to_string(), name.to_string(), revision.to_string())),
+        )
+    };
+    let error_response = |err: gordo_controller::errors::Error| async move {
+        let resp = err.error_response();
+        let status = resp.status();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+    };
+
+    // Refused before requesting the API server
+    let err = rollback("test-project-name", "1").await.err().unwrap();
+    let (status, body) = error_response(err).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "Can't roll back: revision 1 is not in the history");
+
+    let err = rollback("other-project", "1").await.err().unwrap();
+    let (status, body) = error_response(err).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "gordo 'default/other-project' not found");
+}
+
+#[tokio::test]
+async fn test_webhook_routes_apart_from_api() {
+    let mut gordo: Gordo = helpers::deserialize_config("example-gordo.yaml");
+    gordo.metadata.namespace = Some("default".to_string());
+    let data = cached_app_state(vec![gordo]).await;
+    let api = test::init_service(
+        actix_web::App::new()
+            .app_data(data.clone())
+            .configure(views::api_routes),
+    )
+    .await;
+    let webhooks = test::init_service(
+        actix_web::App::new()
+            .app_data(data.clone())
+            .configure(views::webhook_routes),
+    )
+    .await;
+    let status = |uri: &str| test::TestRequest::post().uri(uri).to_request();
+
+    // The admission webhooks are only served on the webhook port
+    for uri in ["/validate/gordo", "/mutate/gordo"] {
+        assert_eq!(
+            test::call_service(&api, status(uri)).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_ne!(
+            test::call_service(&webhooks, status(uri)).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+    // The API server reaching the webhooks can't call the API
+    for uri in [
+        "/gordos/test-project-name/rebuild",
+        "/gordos/test-project-name/rollback/1",
+        "/gordos/test-project-name/suspend",
+        "/gordos/test-project-name/resume",
+    ] {
+        assert_eq!(
+            test::call_service(&webhooks, status(uri)).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_ne!(
+            test::call_service(&api, status(uri)).await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+}
+
+// Helper for just this module: app state with the cache filled with these gordos, without a k8s cluster
+async fn cached_app_state(gordos: Vec<Gordo>) -> web::Data<AppState> {
+    async fn store<K>(objs: Vec<K>) -> IndexedStore<K>
+    where
+        K: 'static
+            + kube::Resource<DynamicType = ()>
+            + Clone
+            + serde::de::DeserializeOwned
+            + std::fmt::Debug
+            + Send
+            + Sync,
+    {
+        let events = vec![Ok(watcher::Event::Restarted(objs))];
+        let (store, reflector) = IndexedStore::from_watchers(vec![futures::stream::iter(events)]);
+        reflector.await;
+        store
+    }
+    let cache = Cache {
+        gordos: store(gordos).await,
+        models: store(vec![]).await,
+        workflows: store(vec![]).await,
+        pods: store(vec![]).await,
+    };
+    // Never connected to
+    let client = kube::Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+    web::Data::new(views::AppState {
+        client,
+        config: helpers::config(vec![]),
+        cache,
+        default_namespace: "default".to_string(),
+    })
+}
+
 // Helper for just this module: loading app state for testing
 async fn app_state() -> web::Data<AppState> {
     let client = helpers::client().await;
-    web::Data::new(views::AppState { client: client.clone() })
+    let config = helpers::config(vec![]);
+    let (cache, reflectors) = Cache::new(&client, &config.watch_namespaces);
+    tokio::spawn(reflectors);
+    while !cache.is_ready() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    web::Data::new(views::AppState {
+        client: client.clone(),
+        config,
+        cache,
+        default_namespace: "default".to_string(),
+    })
 }